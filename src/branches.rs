@@ -1,97 +1,650 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use clap::{App, Arg, SubCommand};
 use colored::*;
-use git2::{Error as GitError, Repository};
+use git2::{Branch, Commit, Error as GitError, ErrorCode, Repository};
 use git2::BranchType::Local;
 
+use crate::clone::GitRepo;
+use crate::conf::RepoSelection;
+use crate::dir::{describe_repo, DirectoryTreeOptions};
+use crate::git::GitAction;
 use crate::input_args::InputArgs;
-use std::process;
+use crate::progress::{ProgressReporter, ProgressTracker};
 
 pub fn sub_command<'a, 'b>() -> App<'a, 'b> {
     SubCommand::with_name("branches")
         .arg(
             Arg::with_name("repo_path")
                 .short("r")
-                .default_value(".")
                 .takes_value(true)
-                .help("path of the repo where branches are to be compared. Defaults to '.'"),
+                .multiple(true)
+                .number_of_values(1)
+                .help("path from which the git repos, whose branches are to be compared, are looked up. \
+                Can be passed multiple times. Defaults to the roots in the conf or '.'"),
         )
         .arg(
             Arg::with_name("main_branch")
                 .short("b")
-                .default_value("origin/master")
                 .takes_value(true)
-                .help("the main branch against which other local branches are to be compared. Defaults to 'origin/master'"),
+                .help("the main branch against which other local branches are to be compared. \
+                Defaults to the mainBranch of the repo in the conf file, else to the default branch of origin"),
+        )
+        .arg(
+            Arg::with_name("traverse-hidden")
+                .short("i")
+                .help("traverse through hidden directories also"),
+        )
+        .arg(
+            Arg::with_name("nested")
+                .long("nested")
+                .help("also find the repos nested in the worktree of other repos"),
+        )
+        .arg(
+            Arg::with_name("merged")
+                .long("merged")
+                .help("only list the local branches which are merged into the main branch, including squash merges"),
+        )
+        .arg(
+            Arg::with_name("delete_merged")
+                .long("delete-merged")
+                .conflicts_with("merged")
+                .help("delete the local branches which are merged into the main branch. \
+                Only lists the branches to be deleted unless --no-dry-run is passed"),
+        )
+        .arg(
+            Arg::with_name("no_dry_run")
+                .long("no-dry-run")
+                .requires("delete_merged")
+                .help("actually delete the merged branches"),
+        )
+        .arg(
+            Arg::with_name("include_unpushed")
+                .long("include-unpushed")
+                .requires("delete_merged")
+                .help("also delete squash merged branches whose commits are not on any remote, \
+                like local only branches or branches whose upstream is gone"),
+        )
+        .arg(
+            Arg::with_name("sort")
+                .long("sort")
+                .takes_value(true)
+                .possible_values(&["name", "age", "ahead"])
+                .default_value("name")
+                .help("order of the branches in a repo. 'age' lists the oldest first and 'ahead' the most ahead first"),
+        )
+        .arg(
+            Arg::with_name("older_than")
+                .long("older-than")
+                .takes_value(true)
+                .validator(|str| parse_duration(&str).map(|_| ()))
+                .help("only list the branches whose last commit is older than the given duration. Eg: 12h, 90d, 8w, 6m, 1y"),
         )
 }
 
-pub fn branches(args: InputArgs) {
-    let root_path = args.get_root_path("repo_path");
-    let matches = args.get_matches();
-    let main_branch = matches.value_of("main_branch").unwrap();
-    let repo = Repository::open(root_path).expect("Failed to open git repo");
+// Durations are given in hours, days, weeks, months or years, where a month is 30 days and a year is 365 days.
+fn parse_duration(duration: &str) -> Result<i64, String> {
+    let unit_seconds = match duration.chars().last() {
+        Some('h') => 60 * 60,
+        Some('d') => 24 * 60 * 60,
+        Some('w') => 7 * 24 * 60 * 60,
+        Some('m') => 30 * 24 * 60 * 60,
+        Some('y') => 365 * 24 * 60 * 60,
+        _ => return Err(format!("duration {} should end with one of h, d, w, m or y", duration)),
+    };
+    let count = duration[..duration.len() - 1]
+        .parse::<i64>()
+        .map_err(|err| format!("invalid duration {}: {}", duration, err))?;
+    if count < 0 {
+        return Err(format!("invalid duration {}: should not be negative", duration));
+    }
+    count
+        .checked_mul(unit_seconds)
+        .ok_or_else(|| format!("invalid duration {}: too large", duration))
+}
 
-    let mut git_branches = GitBranches {
-        repo: repo,
-        main_branch: String::from(main_branch),
+fn describe_age(seconds: i64) -> String {
+    let (count, unit) = match seconds {
+        s if s < 60 => return String::from("just now"),
+        s if s < 60 * 60 => (s / 60, "minute"),
+        s if s < 24 * 60 * 60 => (s / (60 * 60), "hour"),
+        s if s < 30 * 24 * 60 * 60 => (s / (24 * 60 * 60), "day"),
+        s if s < 365 * 24 * 60 * 60 => (s / (30 * 24 * 60 * 60), "month"),
+        s => (s / (365 * 24 * 60 * 60), "year"),
     };
+    if count == 1 {
+        format!("{} {} ago", count, unit)
+    } else {
+        format!("{} {}s ago", count, unit)
+    }
+}
 
-    let branches = git_branches.get_branches().unwrap_or_else(|err| {
-        println!("{} {}", "Failed to get local branches. Err:".red(), err);
-        process::exit(1);
-    });
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum SortBy {
+    Name,
+    Age,
+    Ahead,
+}
 
-    branches
+impl SortBy {
+    fn from_str(sort: &str) -> SortBy {
+        match sort {
+            "age" => SortBy::Age,
+            "ahead" => SortBy::Ahead,
+            _ => SortBy::Name,
+        }
+    }
+
+    fn sort(self, branches: &mut [BranchStatus]) {
+        match self {
+            SortBy::Name => branches.sort_by(|a, b| a.name.cmp(&b.name)),
+            SortBy::Age => branches.sort_by_key(|branch| branch.last_commit.time),
+            SortBy::Ahead => branches.sort_by_key(|branch| Reverse(branch.ahead)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum BranchesMode {
+    All,
+    Merged,
+    DeleteMerged { dry_run: bool, include_unpushed: bool },
+}
+
+// Tried in order when the default branch of origin is not known.
+const DEFAULT_MAIN_BRANCHES: &[&str] = &["origin/main", "origin/master", "origin/develop", "origin/trunk"];
+
+// Branches of each repo, keyed by the repo path so that the report is ordered.
+type BranchesReport = Arc<Mutex<BTreeMap<PathBuf, Vec<BranchStatus>>>>;
+
+pub fn branches(args: InputArgs, dir_options: DirectoryTreeOptions, clone_repos: Vec<GitRepo>, selection: RepoSelection) {
+    let matches = args.get_matches();
+    let filter_hidden = matches.is_present("traverse-hidden");
+    let main_branch = matches.value_of("main_branch");
+    let mode = if matches.is_present("delete_merged") {
+        BranchesMode::DeleteMerged {
+            dry_run: !matches.is_present("no_dry_run"),
+            include_unpushed: matches.is_present("include_unpushed"),
+        }
+    } else if matches.is_present("merged") {
+        BranchesMode::Merged
+    } else {
+        BranchesMode::All
+    };
+    let sort_by = SortBy::from_str(matches.value_of("sort").unwrap());
+    let older_than = matches
+        .value_of("older_than")
+        .map(|duration| parse_duration(duration).expect("failed to parse duration"));
+
+    // Repos are matched with their conf entries by their canonical path.
+    let conf_main_branches: HashMap<PathBuf, String> = clone_repos
         .into_iter()
-        .for_each(|branch| {
-            if branch == "master" {
-                return;
+        .filter_map(|repo| {
+            let main_branch = repo.main_branch?;
+            fs::canonicalize(repo.local_path).ok().map(|path| (path, main_branch))
+        })
+        .collect();
+
+    let dir_tree_with_options = DirectoryTreeOptions {
+        filter_hidden,
+        nested: matches.is_present("nested"),
+        ..dir_options
+    };
+
+    let roots = args.get_root_paths("repo_path", &dir_tree_with_options.roots);
+
+    let report: BranchesReport = Arc::new(Mutex::new(BTreeMap::new()));
+    let multi_bars = ProgressTracker::new(matches.value_of("jobs").and_then(|e| e.parse().ok()));
+    dir_tree_with_options
+        .find_repos(&roots)
+        .filter(|dir| selection.contains(dir))
+        .map(|dir| {
+            let main_branch = main_branch.map(String::from).or_else(|| {
+                fs::canonicalize(&dir)
+                    .ok()
+                    .and_then(|path| conf_main_branches.get(&path).cloned())
+            });
+            GitBranches {
+                dir,
+                main_branch,
+                mode,
+                sort_by,
+                older_than,
+                report: Arc::clone(&report),
             }
-            git_branches.compare_to_main_branch(branch.as_str())
         })
+        .for_each(|branches| multi_bars.start_task(branches));
+    multi_bars.join().unwrap();
+
+    print_report(&report.lock().unwrap());
+}
+
+fn print_report(report: &BTreeMap<PathBuf, Vec<BranchStatus>>) {
+    report
+        .iter()
+        .filter(|(_, branches)| !branches.is_empty())
+        .for_each(|(dir, branches)| {
+            println!();
+            println!("{}", describe_repo(dir).blue());
+            branches.iter().for_each(|branch| println!("  {}", branch.describe()));
+        });
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Merge {
+    Merged,
+    SquashMerged,
+}
+
+// The remote tracking branch configured as the upstream of a local branch.
+enum Upstream {
+    Tracking { name: String, ahead: usize, behind: usize },
+    // Configured, but deleted on the remote.
+    Gone(String),
+    // Not configured, but a remote has a branch with the same name, like after pushing without `-u`.
+    Untracked { name: String, pushed: bool },
+    // Never pushed.
+    LocalOnly,
+}
+
+impl Upstream {
+    // Whether the branch has commits which are only in the local repo.
+    fn has_unpushed_commits(&self) -> bool {
+        match self {
+            Upstream::Tracking { ahead, .. } => *ahead > 0,
+            Upstream::Untracked { pushed, .. } => !pushed,
+            Upstream::Gone(_) | Upstream::LocalOnly => true,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Upstream::Tracking { name, ahead, behind } => {
+                let mut upstream_status = vec![];
+                if *ahead > 0 {
+                    upstream_status.push(format!("{} ahead", ahead));
+                }
+                if *behind > 0 {
+                    upstream_status.push(format!("{} behind", behind));
+                }
+                if upstream_status.is_empty() {
+                    format!("[{}]", name)
+                } else {
+                    format!("[{}: {}]", name, upstream_status.join(", "))
+                }
+            }
+            Upstream::Gone(name) => format!("[{}: {}]", name, "gone".red()),
+            Upstream::Untracked { name, .. } => format!("[{}: {}]", name, "not tracked".dimmed()),
+            Upstream::LocalOnly => format!("[{}]", "local only".yellow()),
+        }
+    }
+}
+
+struct LastCommit {
+    // Seconds since the epoch.
+    time: i64,
+    author: String,
+    summary: String,
+}
+
+impl LastCommit {
+    fn from_commit(commit: &Commit) -> LastCommit {
+        LastCommit {
+            time: commit.time().seconds(),
+            author: commit.author().name().unwrap_or_default().to_string(),
+            summary: commit.summary().unwrap_or_default().to_string(),
+        }
+    }
+
+    fn age(&self) -> i64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or(self.time);
+        now - self.time
+    }
+}
+
+pub struct BranchStatus {
+    name: String,
+    last_commit: LastCommit,
+    ahead: usize,
+    behind: usize,
+    upstream: Upstream,
+    merge: Option<Merge>,
+    deletion: Option<String>,
+}
+
+impl BranchStatus {
+    fn describe(&self) -> String {
+        let mut branch_status = vec![];
+        if self.ahead > 0 {
+            let ahead = format!("{} ahead", self.ahead);
+            branch_status.push(format!("{}", ahead.green()));
+        }
+
+        if self.behind > 0 {
+            let behind = format!("{} behind", self.behind);
+            branch_status.push(format!("{}", behind.yellow()));
+        }
+
+        match self.merge {
+            Some(Merge::Merged) => branch_status.push(format!("{}", "merged".cyan())),
+            Some(Merge::SquashMerged) => branch_status.push(format!("{}", "squash merged".cyan())),
+            None => {}
+        }
+
+        if let Some(deletion) = &self.deletion {
+            branch_status.push(deletion.clone());
+        }
+
+        if branch_status.is_empty() {
+            branch_status.push("up to date".to_string());
+        }
+        format!(
+            "{}: {} {} {} {}: {}",
+            self.name.blue(),
+            branch_status.join(", "),
+            self.upstream.describe(),
+            describe_age(self.last_commit.age()).dimmed(),
+            self.last_commit.author.dimmed(),
+            self.last_commit.summary
+        )
+    }
 }
 
 pub struct GitBranches {
-    repo: Repository,
-    main_branch: String,
+    dir: PathBuf,
+    main_branch: Option<String>,
+    mode: BranchesMode,
+    sort_by: SortBy,
+    // Seconds. Only branches whose last commit is older are reported.
+    older_than: Option<i64>,
+    report: BranchesReport,
 }
 
 impl GitBranches {
-    fn get_branches(&mut self) -> Result<Vec<String>, GitError> {
-        let local_branches = self.repo.branches(Some(Local))?;
-        let mut branch_vec = vec![];
-        local_branches.for_each(|br| {
-            let branch_ref = br.expect("Failed to get branch");
-            let branch_name = branch_ref.0.name().expect("Failed to get valid name of branch")
-                .expect("Failed to convert branch name to string");
-            branch_vec.push(branch_name.to_string())
+    fn upstream_status(repo: &Repository, name: &str, branch: &Branch, head: &Commit) -> Result<Upstream, GitError> {
+        let reference_name = match branch.get().name() {
+            Some(name) => name,
+            None => return Ok(Upstream::LocalOnly),
+        };
+        let upstream_name = match repo.branch_upstream_name(reference_name) {
+            Ok(name) => name,
+            Err(_) => return GitBranches::untracked_status(repo, name, head),
+        };
+        let upstream_name = upstream_name.as_str().unwrap_or_default();
+        let short_name = upstream_name.trim_start_matches("refs/remotes/").to_string();
+
+        match repo.find_reference(upstream_name) {
+            Ok(upstream) => {
+                let (ahead, behind) = repo.graph_ahead_behind(head.id(), upstream.peel_to_commit()?.id())?;
+                Ok(Upstream::Tracking { name: short_name, ahead, behind })
+            }
+            Err(_) => Ok(Upstream::Gone(short_name)),
+        }
+    }
+
+    // Looks for a branch with the same name on the remotes, origin first.
+    fn untracked_status(repo: &Repository, name: &str, head: &Commit) -> Result<Upstream, GitError> {
+        let remotes = repo.remotes()?;
+        let mut remotes: Vec<&str> = remotes.iter().flatten().collect();
+        remotes.sort_by_key(|remote| *remote != "origin");
+        for remote in remotes {
+            let remote_branch = format!("{}/{}", remote, name);
+            if let Ok(reference) = repo.find_reference(&format!("refs/remotes/{}", remote_branch)) {
+                let remote_head = reference.peel_to_commit()?.id();
+                let pushed = remote_head == head.id() || repo.graph_descendant_of(remote_head, head.id())?;
+                return Ok(Upstream::Untracked { name: remote_branch, pushed });
+            }
+        }
+        Ok(Upstream::LocalOnly)
+    }
+
+    // A branch whose commits are all in the main branch is merged.
+    // Squash merges leave the commits out. So the branch is also treated as merged
+    // if merging it into the main branch would not change the tree of the main branch.
+    // A branch which changes nothing, like one with only empty commits or a commit and its revert,
+    // would not change the main branch either, but is not merged.
+    // Squash merges are only looked for when asked, as merging every branch is slow.
+    fn merge_status(
+        repo: &Repository,
+        branch: &Commit,
+        main_branch: &Commit,
+        ahead: usize,
+        check_squash: bool,
+    ) -> Result<Option<Merge>, GitError> {
+        if ahead == 0 {
+            return Ok(Some(Merge::Merged));
+        }
+        if !check_squash {
+            return Ok(None);
+        }
+
+        // Branches with unrelated history, like an orphan gh-pages branch, have no merge base.
+        let merge_base = match repo.merge_base(branch.id(), main_branch.id()) {
+            Ok(merge_base) => repo.find_commit(merge_base)?,
+            Err(ref err) if err.code() == ErrorCode::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        if merge_base.tree_id() == branch.tree_id() {
+            return Ok(None);
+        }
+
+        let mut index = repo.merge_commits(main_branch, branch, None)?;
+        if index.has_conflicts() {
+            return Ok(None);
+        }
+        if index.write_tree_to(repo)? == main_branch.tree_id() {
+            return Ok(Some(Merge::SquashMerged));
+        }
+        Ok(None)
+    }
+
+    // The default branch of origin is what `refs/remotes/origin/HEAD` points to. It is set while cloning,
+    // but not for repos which were initialized and then pushed. The common default branches are tried then.
+    fn resolve_main_branch(&self, repo: &Repository) -> Result<String, GitError> {
+        if let Some(main_branch) = &self.main_branch {
+            return Ok(main_branch.clone());
+        }
+        let origin_head = repo.find_reference("refs/remotes/origin/HEAD").ok().and_then(|reference| {
+            reference
+                .symbolic_target()
+                .map(|target| target.trim_start_matches("refs/remotes/").to_string())
         });
-        Ok(branch_vec)
+        origin_head
+            .or_else(|| {
+                DEFAULT_MAIN_BRANCHES
+                    .iter()
+                    .find(|branch| repo.find_reference(&format!("refs/remotes/{}", branch)).is_ok())
+                    .map(|branch| branch.to_string())
+            })
+            .ok_or_else(|| {
+                GitError::from_str(&format!(
+                    "could not detect the default branch of origin, tried {}. Pass -b or set mainBranch in the conf",
+                    DEFAULT_MAIN_BRANCHES.join(", ")
+                ))
+            })
     }
 
-    fn compare_to_main_branch(&mut self, branch: &str) {
-        let mut branch_status = vec![];
-        self.repo.revparse_single(branch).map_or_else(|_err| {
-            print!("{} {} {}", "No such branch".red(), branch.red(), "found".red());
-        }, |reference|{
-            let head_ref = reference.id();
-            let (is_ahead, is_behind) = self.repo
-                .revparse_ext(self.main_branch.as_str())
-                .ok()
-                .and_then(|(upstream, _)| self.repo.graph_ahead_behind(head_ref, upstream.id()).ok())
-                .unwrap_or((0, 0));
-
-            if is_ahead > 0 {
-                let ahead = format!("{} ahead", is_ahead);
-                let ahead_string_colored = format!("{}", ahead.green());
-                branch_status.push(ahead_string_colored);
+    // The local counterpart of a remote tracking main branch like `origin/main` is `main`.
+    fn local_main_branch<'a>(repo: &Repository, main_branch: &'a str) -> &'a str {
+        let mut parts = main_branch.splitn(2, '/');
+        match (parts.next(), parts.next()) {
+            (Some(remote), Some(branch)) if repo.find_remote(remote).is_ok() => branch,
+            _ => main_branch,
+        }
+    }
+}
+
+impl GitAction for GitBranches {
+    fn get_name(&self) -> String {
+        describe_repo(&self.dir)
+    }
+
+    fn git_action(&mut self, _progress: &ProgressReporter) -> Result<String, GitError> {
+        let repo = Repository::open(self.dir.clone())?;
+        // Bare and mirror repos have no remote tracking branches to compare against.
+        if repo.is_bare() {
+            return Ok(format!("{}", "Skipped as it is a bare repository".yellow()));
+        }
+        let main_branch_name = self.resolve_main_branch(&repo)?;
+        let local_main_branch = GitBranches::local_main_branch(&repo, &main_branch_name);
+        let (main_branch, _) = repo.revparse_ext(main_branch_name.as_str())?;
+        let main_branch_commit = main_branch.peel_to_commit()?;
+
+        let mut branches = vec![];
+        for branch in repo.branches(Some(Local))? {
+            let (mut branch, _) = branch?;
+            let name = match branch.name()? {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            if name == local_main_branch {
+                continue;
             }
 
-            if is_behind > 0 {
-                let behind = format!("{} behind", is_behind);
-                let behind_string_colored = format!("{}", behind.yellow());
-                branch_status.push(behind_string_colored);
+            let head = branch.get().peel_to_commit()?;
+            let last_commit = LastCommit::from_commit(&head);
+            if let Some(older_than) = self.older_than {
+                if last_commit.age() <= older_than {
+                    continue;
+                }
             }
-            println!("{} {}: {}", "Branch".blue(), branch.blue(), branch_status.join(", "));
-        });
+            let (ahead, behind) = repo.graph_ahead_behind(head.id(), main_branch_commit.id())?;
+            let check_squash = self.mode != BranchesMode::All;
+            let merge = GitBranches::merge_status(&repo, &head, &main_branch_commit, ahead, check_squash)?;
+            let upstream = GitBranches::upstream_status(&repo, &name, &branch, &head)?;
+            if self.mode != BranchesMode::All && merge.is_none() {
+                continue;
+            }
+
+            let deletion = match self.mode {
+                BranchesMode::DeleteMerged { .. } if branch.is_head() => {
+                    Some(format!("{}", "not deleted as it is checked out".yellow()))
+                }
+                // The commits of a squash merged branch are not in the main branch.
+                BranchesMode::DeleteMerged { include_unpushed: false, .. }
+                if merge == Some(Merge::SquashMerged) && upstream.has_unpushed_commits() => {
+                    Some(format!("{}", "not deleted as its commits are not pushed, pass --include-unpushed".yellow()))
+                }
+                BranchesMode::DeleteMerged { dry_run: true, .. } => Some(format!("{}", "would be deleted".yellow())),
+                BranchesMode::DeleteMerged { dry_run: false, .. } => {
+                    branch.delete()?;
+                    Some(format!("{}", "deleted".red()))
+                }
+                _ => None,
+            };
+            branches.push(BranchStatus {
+                name,
+                last_commit,
+                ahead,
+                behind,
+                upstream,
+                merge,
+                deletion,
+            });
+        }
+        self.sort_by.sort(&mut branches);
+
+        let summary = match self.mode {
+            BranchesMode::All => {
+                let ahead_count = branches.iter().filter(|branch| branch.ahead > 0).count();
+                format!(
+                    "{} {} {} {}",
+                    branches.len(),
+                    "local branches,".green(),
+                    ahead_count,
+                    format!("ahead of {}", main_branch_name).green()
+                )
+            }
+            _ => format!(
+                "{} {}",
+                branches.len(),
+                format!("local branches merged into {}", main_branch_name).green()
+            ),
+        };
+        self.report.lock().unwrap().insert(self.dir.clone(), branches);
+        Ok(summary)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use git2::{Oid, Signature};
+    use std::env::temp_dir;
+
+    fn init_repo(name: &str) -> Repository {
+        let path = temp_dir().join(format!("gg-branches-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        Repository::init(path).unwrap()
+    }
+
+    // Commits a single file with the given content on top of the parents, without moving any branch.
+    fn commit(repo: &Repository, message: &str, content: &str, parents: &[&Commit]) -> Oid {
+        let blob = repo.blob(content.as_bytes()).unwrap();
+        let mut tree = repo.treebuilder(None).unwrap();
+        tree.insert("file", blob, 0o100_644).unwrap();
+        let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+        let signature = Signature::now("gg", "gg@example.com").unwrap();
+        repo.commit(None, &signature, &signature, message, &tree, parents).unwrap()
+    }
+
+    #[test]
+    fn should_not_treat_branches_with_unrelated_history_as_merged() {
+        let repo = init_repo("unrelated");
+        let main_branch = repo.find_commit(commit(&repo, "main", "main", &[])).unwrap();
+        let orphan = repo.find_commit(commit(&repo, "gh-pages", "gh-pages", &[])).unwrap();
+
+        assert_eq!(GitBranches::merge_status(&repo, &orphan, &main_branch, 1, true).unwrap(), None);
+        assert_eq!(GitBranches::merge_status(&repo, &orphan, &main_branch, 1, false).unwrap(), None);
+        fs::remove_dir_all(repo.path().parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn should_only_look_for_squash_merges_when_asked() {
+        let repo = init_repo("squash");
+        let base = repo.find_commit(commit(&repo, "base", "base", &[])).unwrap();
+        let branch = repo.find_commit(commit(&repo, "feature", "feature", &[&base])).unwrap();
+        let main_branch = repo.find_commit(commit(&repo, "squashed feature", "feature", &[&base])).unwrap();
+
+        assert_eq!(
+            GitBranches::merge_status(&repo, &branch, &main_branch, 1, true).unwrap(),
+            Some(Merge::SquashMerged)
+        );
+        assert_eq!(GitBranches::merge_status(&repo, &branch, &main_branch, 1, false).unwrap(), None);
+        fs::remove_dir_all(repo.path().parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn should_parse_durations_in_seconds() {
+        assert_eq!(parse_duration("12h"), Ok(12 * 60 * 60));
+        assert_eq!(parse_duration("90d"), Ok(90 * 24 * 60 * 60));
+        assert_eq!(parse_duration("8w"), Ok(8 * 7 * 24 * 60 * 60));
+        assert_eq!(parse_duration("6m"), Ok(6 * 30 * 24 * 60 * 60));
+        assert_eq!(parse_duration("1y"), Ok(365 * 24 * 60 * 60));
+        assert_eq!(parse_duration("0d"), Ok(0));
+    }
+
+    #[test]
+    fn should_reject_invalid_durations() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("12").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("1.5d").is_err());
+        assert!(parse_duration("-1d").is_err());
+    }
+
+    #[test]
+    fn should_reject_durations_which_overflow() {
+        assert!(parse_duration("99999999999999999y").is_err());
+        assert!(parse_duration("99999999999999999999y").is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use clap::{App, Arg, SubCommand};
+use colored::*;
+use git2::build::CheckoutBuilder;
+use git2::{BranchType, Error as GitError, Repository, StatusOptions};
+use std::path::PathBuf;
+
+use crate::conf::RepoSelection;
+use crate::dir::{describe_repo, DirectoryTreeOptions};
+use crate::git::GitAction;
+use crate::input_args::InputArgs;
+use crate::progress::{ProgressReporter, ProgressTracker};
+
+pub fn sub_command<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("checkout")
+        .arg(
+            Arg::with_name("branch")
+                .index(1)
+                .required_unless("new_branch")
+                .help("the local or remote tracking branch to switch to in every repo"),
+        )
+        .arg(
+            Arg::with_name("new_branch")
+                .short("b")
+                .takes_value(true)
+                .conflicts_with("branch")
+                .help("create the branch from the current HEAD and switch to it in every repo"),
+        )
+        .arg(
+            Arg::with_name("PATH")
+                .short("f")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("path at which to look for the git repos. Can be passed multiple times. Defaults to the roots in the conf or '.'"),
+        )
+        .arg(
+            Arg::with_name("traverse-hidden")
+                .short("i")
+                .help("traverse through hidden directories also"),
+        )
+        .arg(
+            Arg::with_name("nested")
+                .long("nested")
+                .help("also find the repos nested in the worktree of other repos"),
+        )
+}
+
+pub fn checkout(args: InputArgs, dir_options: DirectoryTreeOptions, selection: RepoSelection) {
+    let matches = args.get_matches();
+    let filter_hidden = matches.is_present("traverse-hidden");
+    let (branch, create) = match matches.value_of("new_branch") {
+        Some(branch) => (branch, true),
+        None => (matches.value_of("branch").unwrap(), false),
+    };
+
+    let dir_tree_with_options = DirectoryTreeOptions {
+        filter_hidden,
+        nested: matches.is_present("nested"),
+        ..dir_options
+    };
+
+    let roots = args.get_root_paths("PATH", &dir_tree_with_options.roots);
+
+    let multi_bars = ProgressTracker::new(matches.value_of("jobs").and_then(|e| e.parse().ok()));
+    dir_tree_with_options
+        .find_repos(&roots)
+        .filter(|dir| selection.contains(dir))
+        .map(|dir| GitCheckout {
+            dir,
+            branch: branch.to_string(),
+            create,
+        })
+        .for_each(|checkout| multi_bars.start_task(checkout));
+    multi_bars.join().unwrap();
+}
+
+pub struct GitCheckout {
+    dir: PathBuf,
+    branch: String,
+    create: bool,
+}
+
+impl GitCheckout {
+    // Untracked files are carried over by a checkout. So only changes to tracked files make a worktree dirty.
+    fn is_dirty(repo: &Repository) -> Result<bool, GitError> {
+        let mut opts = StatusOptions::new();
+        opts.include_ignored(false)
+            .include_untracked(false)
+            .exclude_submodules(true);
+        let statuses = repo.statuses(Some(&mut opts))?;
+        Ok(statuses.iter().any(|e| e.status() != git2::Status::CURRENT))
+    }
+
+    // Creates a local branch tracking the remote branch of the same name, if there is one.
+    fn track_remote_branch(&self, repo: &Repository) -> Result<bool, GitError> {
+        for remote in repo.remotes()?.iter().flatten() {
+            let remote_branch_name = format!("{}/{}", remote, self.branch);
+            if let Ok(remote_branch) = repo.find_branch(&remote_branch_name, BranchType::Remote) {
+                let commit = remote_branch.get().peel_to_commit()?;
+                let mut branch = repo.branch(&self.branch, &commit, false)?;
+                branch.set_upstream(Some(&remote_branch_name))?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl GitAction for GitCheckout {
+    fn get_name(&self) -> String {
+        describe_repo(&self.dir)
+    }
+
+    fn git_action(&mut self, _progress: &ProgressReporter) -> Result<String, GitError> {
+        let repo = Repository::open(self.dir.clone())?;
+        if repo.is_bare() {
+            return Ok(format!("{}", "Skipped as it is a bare repository".yellow()));
+        }
+        let reference_name = format!("refs/heads/{}", self.branch);
+
+        if repo.head().ok().and_then(|head| head.name().map(String::from)) == Some(reference_name.clone()) {
+            return Ok(format!("{} {}", "Already on".green(), self.branch));
+        }
+
+        if GitCheckout::is_dirty(&repo)? {
+            return Ok(format!("{}", "Skipped as the worktree has uncommitted changes".yellow()));
+        }
+
+        let exists = repo.find_branch(&self.branch, BranchType::Local).is_ok();
+        if self.create {
+            if exists {
+                return Ok(format!("{} {} {}", "Skipped as branch".yellow(), self.branch.yellow(), "already exists".yellow()));
+            }
+            let head = repo.head()?.peel_to_commit()?;
+            repo.branch(&self.branch, &head, false)?;
+        } else if !exists && !self.track_remote_branch(&repo)? {
+            return Ok(format!("{} {} {}", "Skipped as branch".yellow(), self.branch.yellow(), "was not found".yellow()));
+        }
+
+        let target = repo.revparse_single(&reference_name)?;
+        repo.checkout_tree(&target, Some(CheckoutBuilder::new().safe()))?;
+        repo.set_head(&reference_name)?;
+
+        Ok(if self.create {
+            format!("{} {}", "Switched to a new branch".green(), self.branch)
+        } else {
+            format!("{} {}", "Switched to branch".green(), self.branch)
+        })
+    }
+}
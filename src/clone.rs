@@ -1,17 +1,20 @@
+use std::collections::HashSet;
 use std::path::PathBuf;
+use std::{env, process};
 
 use clap::{App, Arg, SubCommand};
 use colored::*;
 use git2::build::RepoBuilder;
-use git2::{Error as GitError, FetchOptions, RemoteCallbacks};
+use git2::{Cred, Error as GitError, FetchOptions, RemoteCallbacks};
 use serde::{Deserialize, Serialize};
 
 use crate::conf;
 use crate::git::GitAction;
 use crate::input_args::InputArgs;
+use crate::platform::{self, GitPlatform, RepoFilter, RepoOwner};
 use crate::progress::{ProgressReporter, ProgressTracker};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct GitRepo {
     #[serde(alias = "remoteURL")]
     #[serde(rename = "remoteURL")]
@@ -19,6 +22,25 @@ pub struct GitRepo {
     #[serde(alias = "localPath")]
     #[serde(rename = "localPath")]
     pub local_path: String,
+    #[serde(alias = "mainBranch")]
+    #[serde(rename = "mainBranch")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub main_branch: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub remotes: Vec<GitRemote>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct GitRemote {
+    pub name: String,
+    pub url: String,
 }
 
 pub fn sub_command<'a, 'b>() -> App<'a, 'b> {
@@ -37,6 +59,25 @@ pub fn sub_command<'a, 'b>() -> App<'a, 'b> {
                 .multiple(true)
                 .help("the remote git repo url"),
         )
+        .arg(
+            Arg::with_name("platform")
+                .short("p")
+                .default_value("github")
+                .takes_value(true)
+                .help("the remote platform to list repos from. Defaults to github"),
+        )
+        .arg(
+            Arg::with_name("token")
+                .short("t")
+                .takes_value(true)
+                .help("the access token to list private repos from the platform. Defaults to GITHUB_TOKEN env variable"),
+        )
+        .arg(
+            Arg::with_name("ssh")
+                .long("ssh")
+                .help("clone the repos listed from the platform using their ssh url"),
+        )
+        .args(&platform::list_args())
 }
 
 // gg clone -r url1 -r url2 -l local_root_path
@@ -76,10 +117,45 @@ pub fn clone(args: InputArgs, mut clone_repos: Vec<GitRepo>) {
         let repo = GitRepo {
             remote_url: remote_url_string,
             local_path: format!("{}/{}", local_path.to_string(), splits[0]),
+            ..Default::default()
         };
         remotes_from_args.push(repo);
     }
 
+    // Listed repos include private ones. Their https urls are cloned with the token used to list them.
+    let mut listed_token = None;
+    let mut listed_urls = HashSet::new();
+    if let Some(owner) = RepoOwner::from_matches(matches) {
+        let platform = GitPlatform::from_str(matches.value_of("platform").unwrap());
+        let token = matches
+            .value_of("token")
+            .map(String::from)
+            .or_else(|| env::var("GITHUB_TOKEN").ok())
+            .unwrap_or_default();
+        let listed_repos = platform::list_repos(platform, &owner, &token).unwrap_or_else(|err| {
+            println!("{} {}", "Failed listing repos from the platform:".red(), err);
+            process::exit(err.exit_code());
+        });
+
+        let filter = RepoFilter::from_matches(matches);
+        let use_ssh = matches.is_present("ssh");
+        let mut listed_remotes: Vec<GitRepo> = listed_repos
+            .into_iter()
+            .filter(|repo| filter.is_match(repo))
+            .map(|repo| GitRepo {
+                remote_url: if use_ssh { repo.ssh_url } else { repo.clone_url },
+                local_path: format!("{}/{}", local_path, repo.name),
+                ..Default::default()
+            })
+            .collect();
+        listed_urls = listed_remotes.iter().map(|repo| repo.remote_url.clone()).collect();
+        if !token.is_empty() && !use_ssh {
+            listed_token = Some(token);
+        }
+        println!("{} {} {}", "Cloning".blue(), listed_remotes.len(), "remotes listed from the platform".blue());
+        remotes_from_args.append(&mut listed_remotes);
+    }
+
     if clone_repos.is_empty() {
         println!("{}", "No remotes configured in conf file".yellow())
     } else {
@@ -96,6 +172,7 @@ pub fn clone(args: InputArgs, mut clone_repos: Vec<GitRepo>) {
         .into_iter()
         .map(|remote| GitClone {
             use_ssh: remote.remote_url.contains("git@"),
+            token: listed_token.clone().filter(|_| listed_urls.contains(&remote.remote_url)),
             remote_url: remote.remote_url,
             local_path: remote.local_path.into(),
         })
@@ -108,6 +185,8 @@ pub struct GitClone {
     pub remote_url: String,
     pub local_path: PathBuf,
     pub use_ssh: bool,
+    // Used to authenticate https clones of private repos.
+    pub token: Option<String>,
 }
 
 impl GitAction for GitClone {
@@ -123,6 +202,8 @@ impl GitAction for GitClone {
 
         if self.use_ssh {
             callback.credentials(conf::ssh_auth_callback);
+        } else if let Some(token) = &self.token {
+            callback.credentials(move |_url, _username, _allowed| Cred::userpass_plaintext(token, "x-oauth-basic"));
         }
 
         callback.transfer_progress(prog.get_callback());
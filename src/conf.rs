@@ -1,14 +1,24 @@
+use std::collections::{BTreeMap, HashSet};
 use std::error::Error;
-use std::fs;
-use std::path::Path;
+use std::{env, fs};
+use std::path::{Component, Path, PathBuf};
 
+use clap::ArgMatches;
 use colored::Colorize;
 use git2::{Cred, CredentialType, Error as GitError};
-use regex::Regex;
+use regex::{Captures, Regex};
+use reqwest::Url;
+use serde_yaml::{Mapping, Value};
 use crate::SSH_CONF;
 use serde::{Deserialize, Serialize};
 
 use crate::clone::GitRepo;
+use crate::create::RepoOptions;
+use crate::dir::{skip_matcher, RepoSource};
+
+pub const DEFAULT_CONF_FILE: &str = ".ggConf.yaml";
+
+const DEFAULT_SKIP_DIRECTORIES: &[&str] = &[".idea", ".DS_Store"];
 
 // Todo: This will never be serialized. Try removing Serialize.
 #[derive(Debug, Serialize, Deserialize)]
@@ -18,42 +28,370 @@ pub struct GGConf {
     #[serde(default)]
     pub filter_list: Vec<String>,
 
-    #[serde(skip)]
-    pub filter_list_regex: Vec<Regex>,
-
     #[serde(alias = "cloneRepos")]
     #[serde(rename = "cloneRepos")]
     #[serde(default)]
-    // Todo: Add validations on this field. It should not allow empty key/values.
     pub clone_repos: Vec<GitRepo>,
 
     #[serde(alias = "ssh")]
     #[serde(rename = "ssh")]
     #[serde(default)]
     pub ssh_config: Option<SSHConfig>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub roots: Vec<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+
+    #[serde(alias = "respectGitignore")]
+    #[serde(rename = "respectGitignore")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub respect_gitignore: bool,
+
+    #[serde(alias = "create")]
+    #[serde(rename = "create")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub create_options: Option<RepoOptions>,
 }
 
-impl GGConf {
-    pub fn default() -> GGConf {
-        GGConf {
-            filter_list: vec![],
-            filter_list_regex: vec![],
-            clone_repos: vec![],
-            ssh_config: None,
+// Reads only the given conf file. Use `read_conf` to also apply the global conf and env variables.
+pub fn read_conf_file(conf_file: &str) -> Result<GGConf, Box<dyn Error>> {
+    let mut config: GGConf = serde_yaml::from_value(read_layer(Path::new(conf_file))?)?;
+    update_conf_file(&mut config)?;
+    Ok(config)
+}
+
+// Reads the global conf, the workspace conf over it and then the `GG_*` env variables over both.
+pub fn read_conf(workspace_conf_file: &str) -> Result<GGConf, Box<dyn Error>> {
+    let (value, _) = merged_conf(workspace_conf_file)?;
+    let mut config: GGConf = serde_yaml::from_value(value)?;
+    update_conf_file(&mut config)?;
+    Ok(config)
+}
+
+// The conf file passed with `-c`, else the closest `.ggConf.yaml` in the current directory or its parents.
+// Defaults to `.ggConf.yaml` in the current directory when none is found.
+pub fn workspace_conf_file(conf_file: Option<&str>) -> String {
+    if let Some(conf_file) = conf_file {
+        return conf_file.to_string();
+    }
+    if Path::new(DEFAULT_CONF_FILE).exists() {
+        return DEFAULT_CONF_FILE.to_string();
+    }
+    env::current_dir()
+        .ok()
+        .and_then(|current_dir| {
+            current_dir
+                .ancestors()
+                .map(|dir| dir.join(DEFAULT_CONF_FILE))
+                .find(|conf_file| conf_file.is_file())
+        })
+        .map_or(DEFAULT_CONF_FILE.to_string(), |conf_file| conf_file.to_string_lossy().to_string())
+}
+
+// `$XDG_CONFIG_HOME/gg/config.yaml`, which defaults to `~/.config/gg/config.yaml`.
+pub fn global_conf_file() -> Option<PathBuf> {
+    env::var("XDG_CONFIG_HOME")
+        .ok()
+        .filter(|config_home| !config_home.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var("HOME").ok().map(|home| Path::new(&home).join(".config")))
+        .map(|config_home| config_home.join("gg").join("config.yaml"))
+}
+
+// Where each value of the merged conf came from, keyed by the path of the value like `ssh.privateKey`.
+// A value set for a mapping or a sequence applies to everything under it.
+pub type ConfSources = BTreeMap<String, String>;
+
+pub fn merged_conf(workspace_conf_file: &str) -> Result<(Value, ConfSources), Box<dyn Error>> {
+    let mut merged = Value::Mapping(Mapping::new());
+    let mut sources = ConfSources::new();
+
+    let mut layers = vec![];
+    if let Some(global_conf_file) = global_conf_file() {
+        if global_conf_file.exists() && !is_same_file(&global_conf_file, Path::new(workspace_conf_file)) {
+            layers.push(global_conf_file);
         }
     }
+    layers.push(PathBuf::from(workspace_conf_file));
+    for layer in layers {
+        let value = read_layer(&layer)?;
+        merge_value(&mut merged, value, "", &layer.to_string_lossy(), &mut sources);
+    }
+
+    for (path, env_name) in env_override_keys() {
+        if let Ok(env_value) = env::var(&env_name) {
+            let value = env_override_value(path.last().unwrap(), &env_value)
+                .map_err(|err| format!("{}: {}", env_name, err))?;
+            let layer = path.iter().rev().fold(value, |value, key| {
+                let mut mapping = Mapping::new();
+                mapping.insert(Value::from(*key), value);
+                Value::Mapping(mapping)
+            });
+            merge_value(&mut merged, layer, "", &format!("${}", env_name), &mut sources);
+        }
+    }
+    Ok((merged, sources))
 }
 
-pub fn read_conf_file(conf_file: &str) -> Result<GGConf, Box<dyn Error>> {
-    if Path::new(conf_file).exists() {
-        let file = fs::File::open(conf_file)?;
-        let mut config: GGConf = serde_yaml::from_reader(file)?;
-        update_conf_file(&mut config)?;
-        return Ok(config);
+// The conf the commands run with. The default skip directories come first and keys set in no layer have their default value.
+// The merged conf is returned along with it, to tell the values set in a layer from the defaults.
+pub fn effective_conf(workspace_conf_file: &str) -> Result<(Value, Value, ConfSources), Box<dyn Error>> {
+    let (merged, sources) = merged_conf(workspace_conf_file)?;
+    let mut conf: GGConf = serde_yaml::from_value(merged.clone())?;
+    update_conf_file(&mut conf)?;
+    if conf.ssh_config.is_none() {
+        conf.ssh_config = Some(default_ssh_config());
+    }
+
+    let conf_value = serde_yaml::to_value(&conf)?;
+    let defaults = [
+        ("roots", Value::Sequence(vec![])),
+        ("source", Value::from("disk")),
+        ("respectGitignore", Value::Bool(false)),
+        ("create", Value::Mapping(Mapping::new())),
+    ];
+    let mut effective = Mapping::new();
+    for key in TOP_LEVEL_KEYS {
+        let value = conf_value
+            .get(*key)
+            .cloned()
+            .or_else(|| defaults.iter().find(|(name, _)| name == key).map(|(_, value)| value.clone()));
+        if let Some(value) = value {
+            effective.insert(Value::from(*key), value);
+        }
+    }
+    Ok((Value::Mapping(effective), merged, sources))
+}
+
+// The path of an effective conf value in the merged conf, or None for the default skip directories.
+pub fn merged_conf_path(path: &str) -> Option<String> {
+    let index = path.strip_prefix("skipDirectories[").and_then(|rest| rest.strip_suffix(']'));
+    match index.and_then(|index| index.parse::<usize>().ok()) {
+        Some(index) if index < DEFAULT_SKIP_DIRECTORIES.len() => None,
+        Some(index) => Some(format!("skipDirectories[{}]", index - DEFAULT_SKIP_DIRECTORIES.len())),
+        None => Some(path.to_string()),
+    }
+}
+
+pub fn conf_source<'a>(sources: &'a ConfSources, path: &str) -> Option<&'a str> {
+    sources
+        .iter()
+        .filter(|(key, _)| {
+            path == key.as_str()
+                || (path.starts_with(key.as_str()) && (path[key.len()..].starts_with('.') || path[key.len()..].starts_with('[')))
+        })
+        .max_by_key(|(key, _)| key.len())
+        .map(|(_, source)| source.as_str())
+}
+
+// Each layer is checked on its own, so that errors point to the file and line they are in.
+// The local paths are resolved here, as they are relative to the file they are in.
+fn read_layer(conf_file: &Path) -> Result<Value, Box<dyn Error>> {
+    if !conf_file.exists() {
+        return Ok(Value::Mapping(Mapping::new()));
+    }
+    let content = fs::read_to_string(conf_file)?;
+    let file_name = conf_file.to_string_lossy();
+    serde_yaml::from_str::<GGConf>(&content).map_err(|err| format!("{}: {}", file_name, err))?;
+    let mut value: Value = serde_yaml::from_str(&content)?;
+    if value.is_null() {
+        return Ok(Value::Mapping(Mapping::new()));
+    }
+
+    let conf_dir = conf_file.parent().unwrap_or_else(|| Path::new(""));
+    if let Some(Value::Sequence(roots)) = value.get_mut("roots") {
+        roots.iter_mut().for_each(|root| {
+            if let Value::String(root) = root {
+                *root = resolve_local_path(root, conf_dir);
+            }
+        });
+    }
+    if let Some(Value::Sequence(clone_repos)) = value.get_mut("cloneRepos") {
+        clone_repos.iter_mut().for_each(|repo| {
+            if let Some(Value::String(local_path)) = repo.get_mut("localPath") {
+                *local_path = resolve_local_path(local_path, conf_dir);
+            }
+        });
+    }
+    Ok(value)
+}
+
+// Mappings are merged key by key. Any other value replaces the value of the lower layer.
+fn merge_value(base: &mut Value, layer: Value, path: &str, source: &str, sources: &mut ConfSources) {
+    match (base, layer) {
+        (Value::Mapping(base), Value::Mapping(layer)) => {
+            for (key, value) in layer {
+                let key_path = match (path, key.as_str()) {
+                    ("", Some(name)) => name.to_string(),
+                    (_, Some(name)) => format!("{}.{}", path, name),
+                    _ => continue,
+                };
+                match base.get_mut(&key) {
+                    Some(base_value) => merge_value(base_value, value, &key_path, source, sources),
+                    None => {
+                        set_source(sources, &key_path, source);
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, layer) => {
+            set_source(sources, path, source);
+            *base = layer;
+        }
     }
-    let mut default = GGConf::default();
-    update_conf_file(&mut default)?;
-    Ok(default)
+}
+
+fn set_source(sources: &mut ConfSources, path: &str, source: &str) {
+    let nested = |key: &String| key.starts_with(&format!("{}.", path)) || key.starts_with(&format!("{}[", path));
+    sources.retain(|key, _| !nested(key));
+    sources.insert(path.to_string(), source.to_string());
+}
+
+const LIST_KEYS: &[&str] = &["skipDirectories", "roots", "topics"];
+const BOOL_KEYS: &[&str] = &["respectGitignore", "sshAgent", "private", "autoInit"];
+
+// The keys which can be overridden by env variables, with the name of the env variable.
+// For example `ssh.privateKey` is overridden by `GG_SSH_PRIVATE_KEY` and `ssh.sshAgent` by `GG_SSH_AGENT`.
+pub fn env_override_keys() -> Vec<(Vec<&'static str>, String)> {
+    let mut paths = vec![vec!["skipDirectories"], vec!["roots"], vec!["source"], vec!["respectGitignore"]];
+    SSH_KEYS.iter().for_each(|key| paths.push(vec!["ssh", key]));
+    CREATE_KEYS.iter().for_each(|key| paths.push(vec!["create", key]));
+    paths
+        .into_iter()
+        .map(|path| {
+            let mut words: Vec<String> = path.iter().map(|key| screaming_snake_case(key)).collect();
+            if words.len() > 1 && words[1].starts_with(&format!("{}_", words[0])) {
+                words.remove(0);
+            }
+            let env_name = format!("GG_{}", words.join("_"));
+            (path, env_name)
+        })
+        .collect()
+}
+
+// Lists are comma separated.
+fn env_override_value(key: &str, env_value: &str) -> Result<Value, String> {
+    if LIST_KEYS.contains(&key) {
+        let values = env_value
+            .split(',')
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(Value::from)
+            .collect();
+        return Ok(Value::Sequence(values));
+    }
+    if BOOL_KEYS.contains(&key) {
+        return match env_value.to_lowercase().as_str() {
+            "true" | "1" => Ok(Value::Bool(true)),
+            "false" | "0" => Ok(Value::Bool(false)),
+            _ => Err(format!("expected true or false, found `{}`", env_value)),
+        };
+    }
+    Ok(Value::from(env_value))
+}
+
+fn screaming_snake_case(key: &str) -> String {
+    key.chars().fold(String::new(), |mut name, c| {
+        if c.is_uppercase() && !name.is_empty() {
+            name.push('_');
+        }
+        name.push(c.to_ascii_uppercase());
+        name
+    })
+}
+
+fn is_same_file(first: &Path, second: &Path) -> bool {
+    match (fs::canonicalize(first), fs::canonicalize(second)) {
+        (Ok(first), Ok(second)) => first == second,
+        _ => false,
+    }
+}
+
+// Limits the repos a command acts on to the cloneRepos entries in any of the given groups and with any of the given tags.
+pub struct RepoSelector {
+    groups: Vec<String>,
+    tags: Vec<String>,
+}
+
+impl RepoSelector {
+    pub fn from_matches(matches: &ArgMatches) -> RepoSelector {
+        let values = |name: &str| -> Vec<String> {
+            matches.values_of(name).map_or(vec![], |values| values.map(String::from).collect())
+        };
+        RepoSelector {
+            groups: values("group"),
+            tags: values("tag"),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty() && self.tags.is_empty()
+    }
+
+    pub fn is_selected(&self, repo: &GitRepo) -> bool {
+        let any = |selected: &[String], values: &[String]| selected.is_empty() || values.iter().any(|value| selected.contains(value));
+        any(&self.groups, &repo.groups) && any(&self.tags, &repo.tags)
+    }
+
+    // Repos found on disk are matched with their conf entries by their canonical path.
+    pub fn selection(&self, clone_repos: &[GitRepo]) -> RepoSelection {
+        if self.is_empty() {
+            return RepoSelection { paths: None };
+        }
+        let paths: HashSet<PathBuf> = clone_repos
+            .iter()
+            .filter(|repo| self.is_selected(repo))
+            .filter_map(|repo| fs::canonicalize(&repo.local_path).ok())
+            .collect();
+        if paths.is_empty() {
+            println!("{}", "No repos in cloneRepos match the given --group or --tag".yellow());
+        }
+        RepoSelection { paths: Some(paths) }
+    }
+}
+
+pub struct RepoSelection {
+    paths: Option<HashSet<PathBuf>>,
+}
+
+impl RepoSelection {
+    pub fn contains(&self, dir: &Path) -> bool {
+        match &self.paths {
+            Some(paths) => fs::canonicalize(dir).is_ok_and(|dir| paths.contains(&dir)),
+            None => true,
+        }
+    }
+}
+
+// Expands `~` and env variables like `$HOME` or `${HOME}` in the path. Unset env variables are left as is.
+// Relative paths are resolved against the directory of the conf file.
+fn resolve_local_path(local_path: &str, conf_dir: &Path) -> String {
+    let env_var = Regex::new(r"\$\{(\w+)\}|\$(\w+)").expect("failed to construct regex");
+    let mut expanded = env_var
+        .replace_all(local_path, |captures: &Captures| {
+            let name = captures.get(1).or_else(|| captures.get(2)).unwrap().as_str();
+            env::var(name).unwrap_or_else(|_| captures[0].to_string())
+        })
+        .to_string();
+
+    if expanded == "~" || expanded.starts_with("~/") {
+        if let Ok(home) = env::var("HOME") {
+            expanded = expanded.replacen("~", &home, 1);
+        }
+    }
+
+    let path = Path::new(&expanded);
+    if path.is_relative() && !conf_dir.as_os_str().is_empty() {
+        return conf_dir.join(path).to_string_lossy().to_string();
+    }
+    expanded
 }
 
 fn update_conf_file<'a>(conf: &mut GGConf) -> Result<(), Box<dyn Error>> {
@@ -61,31 +399,305 @@ fn update_conf_file<'a>(conf: &mut GGConf) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn create_filter_list<'a>(conf: &mut GGConf) -> Result<(), Box<dyn Error>> {
-    let mut filter_list = Vec::new();
-    let mut filters = conf.filter_list.clone();
-    let defaults: Vec<String> = [".idea", ".DS_Store"].iter().map(|&s| s.into()).collect();
-    defaults.iter().for_each(|def| {
-        filters.push(def.to_owned());
-    });
-
-    filters.iter().for_each(|ignore| {
-        let re =
-            Regex::new(format!(r".*/{}?*", ignore).as_str()).expect("failed to construct regex");
-        filter_list.push(re);
-    });
+// The defaults come first, so that they can be re-included by negating them, like `!.idea`.
+fn create_filter_list(conf: &mut GGConf) -> Result<(), Box<dyn Error>> {
+    let mut filters: Vec<String> = DEFAULT_SKIP_DIRECTORIES.iter().map(|&s| s.into()).collect();
+    filters.append(&mut conf.filter_list);
+    skip_matcher(Path::new(""), &filters)?;
 
     conf.filter_list = filters;
-    conf.filter_list_regex = filter_list;
     Ok(())
 }
 
+const TOP_LEVEL_KEYS: &[&str] = &["skipDirectories", "roots", "source", "respectGitignore", "cloneRepos", "ssh", "create"];
+const CLONE_REPO_KEYS: &[&str] = &["remoteURL", "localPath", "mainBranch", "remotes", "groups", "tags"];
+const REMOTE_KEYS: &[&str] = &["name", "url"];
+const SSH_KEYS: &[&str] = &["privateKey", "username", "sshAgent"];
+const CREATE_KEYS: &[&str] = &[
+    "private", "org", "description", "homepage", "topics", "defaultBranch",
+    "autoInit", "gitignoreTemplate", "licenseTemplate", "template",
+];
+
+pub struct ConfIssue {
+    pub line: usize,
+    pub message: String,
+    pub is_error: bool,
+}
+
+// Checks the conf file beyond what deserializing it does, and reports the line of every issue.
+// Unknown keys are reported as warnings, since they are ignored while reading the conf.
+pub fn validate_conf(content: &str, conf_dir: &Path) -> Vec<ConfIssue> {
+    let mut issues = vec![];
+    let error = |line: usize, message: String| ConfIssue { line, message, is_error: true };
+    let warning = |line: usize, message: String| ConfIssue { line, message, is_error: false };
+
+    let value: Value = match serde_yaml::from_str(content) {
+        Ok(value) => value,
+        Err(err) => {
+            let line = err.location().map_or(1, |location| location.line());
+            return vec![error(line, err.to_string())];
+        }
+    };
+    let lines = ConfLines::new(content);
+    let root = match value {
+        Value::Null => return issues,
+        Value::Mapping(root) => root,
+        _ => return vec![error(1, "the conf should be a mapping of keys".to_string())],
+    };
+
+    let mut unknown_keys = |mapping: &Mapping, known: &[&str], path: &[Segment]| {
+        mapping.iter().for_each(|(key, _)| {
+            let key = key.as_str().unwrap_or_default();
+            if !known.contains(&key) {
+                let mut key_path = path.to_vec();
+                key_path.push(Segment::Key(key));
+                issues.push(warning(lines.locate(&key_path), format!("unknown key `{}` is ignored", key)));
+            }
+        })
+    };
+    unknown_keys(&root, TOP_LEVEL_KEYS, &[]);
+    if let Some(Value::Mapping(ssh)) = root.get(&Value::from("ssh")) {
+        unknown_keys(ssh, SSH_KEYS, &[Segment::Key("ssh")]);
+    }
+    if let Some(Value::Mapping(create)) = root.get(&Value::from("create")) {
+        unknown_keys(create, CREATE_KEYS, &[Segment::Key("create")]);
+    }
+    let clone_repos = match root.get(&Value::from("cloneRepos")) {
+        Some(Value::Sequence(clone_repos)) => clone_repos.clone(),
+        _ => vec![],
+    };
+    for (index, repo) in clone_repos.iter().enumerate() {
+        let path = [Segment::Key("cloneRepos"), Segment::Index(index)];
+        if let Value::Mapping(repo) = repo {
+            unknown_keys(repo, CLONE_REPO_KEYS, &path);
+            if let Some(Value::Sequence(remotes)) = repo.get(&Value::from("remotes")) {
+                for (remote_index, remote) in remotes.iter().enumerate() {
+                    if let Value::Mapping(remote) = remote {
+                        let remote_path = [path[0].clone(), path[1].clone(), Segment::Key("remotes"), Segment::Index(remote_index)];
+                        unknown_keys(remote, REMOTE_KEYS, &remote_path);
+                    }
+                }
+            }
+        }
+    }
+
+    let string_at = |mapping: &Mapping, key: &str| mapping.get(&Value::from(key)).and_then(Value::as_str).map(String::from);
+    let mut local_paths: Vec<(String, usize)> = vec![];
+    for (index, repo) in clone_repos.iter().enumerate() {
+        let path = [Segment::Key("cloneRepos"), Segment::Index(index)];
+        let repo = match repo {
+            Value::Mapping(repo) => repo,
+            _ => {
+                issues.push(error(lines.locate(&path), format!("cloneRepos[{}] should be a mapping", index)));
+                continue;
+            }
+        };
+        for key in ["remoteURL", "localPath"].iter() {
+            match string_at(repo, key) {
+                Some(ref value) if !value.trim().is_empty() => {}
+                Some(_) => issues.push(error(lines.locate(&[path[0].clone(), path[1].clone(), Segment::Key(key)]),
+                                             format!("cloneRepos[{}]: {} should not be empty", index, key))),
+                None => issues.push(error(lines.locate(&path), format!("cloneRepos[{}]: {} is missing", index, key))),
+            }
+        }
+        if let Some(remote_url) = string_at(repo, "remoteURL") {
+            if !remote_url.trim().is_empty() && !is_valid_remote_url(&remote_url) {
+                issues.push(error(lines.locate(&[path[0].clone(), path[1].clone(), Segment::Key("remoteURL")]),
+                                  format!("cloneRepos[{}]: `{}` is not a valid git url", index, remote_url)));
+            }
+        }
+        if let Some(Value::Sequence(remotes)) = repo.get(&Value::from("remotes")) {
+            for (remote_index, remote) in remotes.iter().enumerate() {
+                let remote_path = [path[0].clone(), path[1].clone(), Segment::Key("remotes"), Segment::Index(remote_index)];
+                let url = match remote {
+                    Value::Mapping(remote) => string_at(remote, "url"),
+                    _ => None,
+                };
+                match url {
+                    Some(ref url) if is_valid_remote_url(url) => {}
+                    Some(url) => issues.push(error(lines.locate(&remote_path),
+                                                   format!("cloneRepos[{}].remotes[{}]: `{}` is not a valid git url", index, remote_index, url))),
+                    None => issues.push(error(lines.locate(&remote_path),
+                                              format!("cloneRepos[{}].remotes[{}]: url is missing", index, remote_index))),
+                }
+            }
+        }
+        if let Some(local_path) = string_at(repo, "localPath") {
+            let line = lines.locate(&[path[0].clone(), path[1].clone(), Segment::Key("localPath")]);
+            let resolved = resolve_local_path(&local_path, conf_dir);
+            let resolved = Path::new(&resolved)
+                .components()
+                .filter(|component| *component != Component::CurDir)
+                .collect::<PathBuf>()
+                .to_string_lossy()
+                .to_string();
+            match local_paths.iter().find(|(path, _)| *path == resolved) {
+                Some((_, first_line)) => issues.push(error(line, format!("duplicate localPath `{}`, already used at line {}", local_path, first_line))),
+                None => local_paths.push((resolved, line)),
+            }
+        }
+    }
+
+    if let Some(Value::Sequence(filters)) = root.get(&Value::from("skipDirectories")) {
+        for (index, filter) in filters.iter().enumerate() {
+            if let Some(Err(err)) = filter.as_str().map(|filter| skip_matcher(Path::new(""), &[filter.to_string()])) {
+                let line = lines.locate(&[Segment::Key("skipDirectories"), Segment::Index(index)]);
+                issues.push(error(line, format!("skipDirectories pattern `{}` is invalid: {}", filter.as_str().unwrap(), err)));
+            }
+        }
+    }
+
+    if let Some(source) = root.get(&Value::from("source")).and_then(Value::as_str) {
+        if RepoSource::from_str(source).is_none() {
+            let line = lines.locate(&[Segment::Key("source")]);
+            issues.push(error(line, format!("source `{}` should be one of conf, disk or both", source)));
+        }
+    }
+
+    if let Some(Value::Sequence(roots)) = root.get(&Value::from("roots")) {
+        for (index, conf_root) in roots.iter().enumerate() {
+            if let Some(conf_root) = conf_root.as_str() {
+                if !Path::new(&resolve_local_path(conf_root, conf_dir)).is_dir() {
+                    let line = lines.locate(&[Segment::Key("roots"), Segment::Index(index)]);
+                    issues.push(warning(line, format!("root `{}` is not a directory", conf_root)));
+                }
+            }
+        }
+    }
+
+    if let Some(Value::Mapping(ssh)) = root.get(&Value::from("ssh")) {
+        let ssh_agent = ssh.get(&Value::from("sshAgent")).and_then(Value::as_bool).unwrap_or(false);
+        if let Some(private_key) = string_at(ssh, "privateKey") {
+            if !ssh_agent && !Path::new(&private_key).exists() {
+                let line = lines.locate(&[Segment::Key("ssh"), Segment::Key("privateKey")]);
+                issues.push(error(line, format!("ssh private key `{}` does not exist", private_key)));
+            }
+        }
+    }
+
+    // Type errors are only found by deserializing. The error is dropped if a check above already reported its line.
+    if let Err(err) = serde_yaml::from_str::<GGConf>(content) {
+        let line = err.location().map_or(1, |location| location.line());
+        if !issues.iter().any(|issue| issue.is_error && issue.line == line) {
+            issues.push(error(line, err.to_string()));
+        }
+    }
+
+    issues.sort_by_key(|issue| issue.line);
+    issues
+}
+
+// Accepts urls with a scheme, scp like urls such as git@github.com:org/repo.git and local paths.
+fn is_valid_remote_url(remote_url: &str) -> bool {
+    if remote_url.contains("://") {
+        return Url::parse(remote_url).map(|url| url.has_host() || url.scheme() == "file").unwrap_or(false);
+    }
+    let scp_like = Regex::new(r"^([\w.-]+@)?[\w.-]+:[^/\\].*$").expect("failed to construct regex");
+    scp_like.is_match(remote_url) || Path::new(remote_url).is_absolute()
+}
+
+#[derive(Clone)]
+enum Segment<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+// Finds the line of a value in the conf file, as serde_yaml does not keep the position of values.
+// This only understands block style yaml. Otherwise the line of the closest parent found is used.
+struct ConfLines<'a> {
+    lines: Vec<&'a str>,
+}
+
+impl<'a> ConfLines<'a> {
+    fn new(content: &'a str) -> ConfLines<'a> {
+        ConfLines { lines: content.lines().collect() }
+    }
+
+    fn is_content(line: &str) -> bool {
+        !line.trim().is_empty() && !line.trim_start().starts_with('#')
+    }
+
+    fn indent(line: &str) -> usize {
+        line.len() - line.trim_start().len()
+    }
+
+    // The indent of the key on the line, which is after the dashes of the sequence items.
+    fn content_indent(line: &str) -> usize {
+        let mut indent = ConfLines::indent(line);
+        let mut rest = line.trim_start();
+        while rest.starts_with("- ") || rest == "-" {
+            let trimmed = rest[1..].trim_start();
+            indent += rest.len() - trimmed.len();
+            rest = trimmed;
+        }
+        indent
+    }
+
+    fn key_of(line: &str) -> &str {
+        let content = &line[ConfLines::content_indent(line)..];
+        content.split(':').next().unwrap_or_default().trim().trim_matches(|c| c == '"' || c == '\'')
+    }
+
+    // Returns the line number, starting from 1.
+    fn locate(&self, path: &[Segment]) -> usize {
+        let mut found = 0;
+        let (mut start, mut end) = (0, self.lines.len());
+        for segment in path {
+            let next = match segment {
+                Segment::Key(key) => self.find_key(start, end, key),
+                Segment::Index(index) => self.find_item(start, end, *index),
+            };
+            match next {
+                Some((line, item_end)) => {
+                    found = line;
+                    start = if let Segment::Key(_) = segment { line + 1 } else { line };
+                    end = item_end;
+                }
+                None => break,
+            }
+        }
+        found + 1
+    }
+
+    fn find_key(&self, start: usize, end: usize, key: &str) -> Option<(usize, usize)> {
+        let indent = (start..end)
+            .filter(|index| ConfLines::is_content(self.lines[*index]))
+            .map(|index| ConfLines::content_indent(self.lines[index]))
+            .min()?;
+        let line = (start..end).find(|index| {
+            let line = self.lines[*index];
+            ConfLines::is_content(line) && ConfLines::content_indent(line) == indent && ConfLines::key_of(line) == key
+        })?;
+        let value_end = (line + 1..end)
+            .find(|index| {
+                let line = self.lines[*index];
+                let line_indent = ConfLines::indent(line);
+                ConfLines::is_content(line)
+                    && (line_indent < indent || (line_indent == indent && !line.trim_start().starts_with('-')))
+            })
+            .unwrap_or(end);
+        Some((line, value_end))
+    }
+
+    fn find_item(&self, start: usize, end: usize, index: usize) -> Option<(usize, usize)> {
+        let dashes: Vec<usize> = (start..end)
+            .filter(|line| self.lines[*line].trim_start().starts_with('-'))
+            .collect();
+        let indent = dashes.iter().map(|line| ConfLines::indent(self.lines[*line])).min()?;
+        let items: Vec<usize> = dashes.into_iter().filter(|line| ConfLines::indent(self.lines[*line]) == indent).collect();
+        let line = *items.get(index)?;
+        Some((line, items.get(index + 1).cloned().unwrap_or(end)))
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct SSHConfig {
     #[serde(alias = "privateKey")]
     #[serde(rename = "privateKey")]
+    #[serde(default = "default_private_key")]
     pub private_key: String,
 
+    #[serde(default = "default_username")]
     pub username: String,
 
     #[serde(alias = "sshAgent")]
@@ -94,6 +706,22 @@ pub struct SSHConfig {
     pub ssh_agent: bool,
 }
 
+pub fn default_private_key() -> String {
+    format!("{}/.ssh/id_rsa", env::var("HOME").expect("HOME env not found"))
+}
+
+pub fn default_username() -> String {
+    String::from("git")
+}
+
+pub fn default_ssh_config() -> SSHConfig {
+    SSHConfig {
+        private_key: default_private_key(),
+        username: default_username(),
+        ssh_agent: false,
+    }
+}
+
 pub fn ssh_auth_callback(_user: &str, _user_from_url: Option<&str>, _cred: CredentialType) -> Result<Cred, GitError> {
     let ssh_conf = SSH_CONF.lock().unwrap();
 
@@ -115,3 +743,156 @@ pub fn ssh_auth_callback(_user: &str, _user_from_url: Option<&str>, _cred: Crede
                   None)
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conf(yaml: &str) -> GGConf {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn should_put_the_default_skip_directories_first() {
+        let mut conf = conf("skipDirectories: [vendor, '!.idea']");
+
+        create_filter_list(&mut conf).unwrap();
+
+        assert_eq!(conf.filter_list, vec![".idea", ".DS_Store", "vendor", "!.idea"]);
+        let matcher = skip_matcher(Path::new("."), &conf.filter_list).unwrap();
+        assert!(!matcher.matched("./.idea", true).is_ignore());
+        assert!(matcher.matched("./.DS_Store", true).is_ignore());
+    }
+
+    #[test]
+    fn should_map_effective_skip_directories_to_the_merged_conf() {
+        assert_eq!(merged_conf_path("skipDirectories[0]"), None);
+        assert_eq!(merged_conf_path("skipDirectories[1]"), None);
+        assert_eq!(merged_conf_path("skipDirectories[2]"), Some(String::from("skipDirectories[0]")));
+        assert_eq!(merged_conf_path("ssh.privateKey"), Some(String::from("ssh.privateKey")));
+        assert_eq!(merged_conf_path("cloneRepos[0].localPath"), Some(String::from("cloneRepos[0].localPath")));
+    }
+
+    #[test]
+    fn should_reject_invalid_skip_directories() {
+        let mut conf = conf("skipDirectories: ['a/**[']");
+
+        assert!(create_filter_list(&mut conf).is_err());
+    }
+
+    fn issues(content: &str) -> Vec<(usize, bool, String)> {
+        validate_conf(content, Path::new("/"))
+            .into_iter()
+            .map(|issue| (issue.line, issue.is_error, issue.message))
+            .collect()
+    }
+
+    #[test]
+    fn should_locate_nested_keys() {
+        let content = "\
+skipDirectories:
+  - vendor
+cloneRepos:
+  - remoteURL: https://github.com/o/a.git
+    localPath: a
+    remotes:
+      - name: origin
+        url: https://github.com/o/a.git
+      - name: fork
+        url: https://github.com/me/a.git
+ssh:
+  username: git
+  privateKey: /home/me/.ssh/id_rsa
+";
+        let lines = ConfLines::new(content);
+
+        assert_eq!(lines.locate(&[Segment::Key("skipDirectories"), Segment::Index(0)]), 2);
+        assert_eq!(lines.locate(&[Segment::Key("cloneRepos"), Segment::Index(0), Segment::Key("localPath")]), 5);
+        let fork = [Segment::Key("cloneRepos"), Segment::Index(0), Segment::Key("remotes"), Segment::Index(1)];
+        assert_eq!(lines.locate(&fork), 9);
+        let fork_url = [fork[0].clone(), fork[1].clone(), fork[2].clone(), fork[3].clone(), Segment::Key("url")];
+        assert_eq!(lines.locate(&fork_url), 10);
+        assert_eq!(lines.locate(&[Segment::Key("ssh"), Segment::Key("privateKey")]), 13);
+    }
+
+    #[test]
+    fn should_locate_the_closest_parent_of_missing_keys() {
+        let content = "\
+cloneRepos:
+  - remoteURL: https://github.com/o/a.git
+    localPath: a
+";
+        let lines = ConfLines::new(content);
+
+        assert_eq!(lines.locate(&[Segment::Key("cloneRepos"), Segment::Index(0), Segment::Key("mainBranch")]), 2);
+        assert_eq!(lines.locate(&[Segment::Key("cloneRepos"), Segment::Index(1)]), 1);
+    }
+
+    #[test]
+    fn should_report_the_lines_of_issues_in_nested_keys() {
+        let content = "\
+cloneRepos:
+  - remoteURL: https://github.com/o/a.git
+    localPath: a
+    remotes:
+      - name: origin
+        url: not a url
+        extra: 1
+ssh:
+  privateKey: /nonexistent/id_rsa
+  sshAgent: false
+  port: 22
+";
+
+        assert_eq!(issues(content), vec![
+            (5, true, "cloneRepos[0].remotes[0]: `not a url` is not a valid git url".to_string()),
+            (7, false, "unknown key `extra` is ignored".to_string()),
+            (9, true, "ssh private key `/nonexistent/id_rsa` does not exist".to_string()),
+            (11, false, "unknown key `port` is ignored".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn should_report_the_lines_of_issues_in_indentless_sequences_with_comments() {
+        let content = "\
+cloneRepos:
+# the first repo
+- remoteURL: \"\"
+  localPath: a
+
+# the second repo, at the same path
+- remoteURL: https://github.com/o/b.git
+  # a comment between keys
+  localPath: ./a
+  mainBrnch: main
+";
+
+        assert_eq!(issues(content), vec![
+            (3, true, "cloneRepos[0]: remoteURL should not be empty".to_string()),
+            (9, true, "duplicate localPath `./a`, already used at line 4".to_string()),
+            (10, false, "unknown key `mainBrnch` is ignored".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn should_report_issues_of_flow_style_entries_at_the_entry() {
+        let content = "\
+cloneRepos:
+  - {remoteURL: https://github.com/o/a.git, localPath: a}
+  - {remoteURL: \"\", localPath: b}
+";
+
+        assert_eq!(issues(content), vec![(3, true, "cloneRepos[1]: remoteURL should not be empty".to_string())]);
+    }
+
+    #[test]
+    fn should_report_the_line_of_syntax_errors() {
+        let content = "cloneRepos:\n  - remoteURL: a\n   localPath: [\n";
+
+        let issues = issues(content);
+
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].1);
+        assert_eq!(issues[0].0, 3);
+    }
+}
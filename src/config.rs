@@ -1,15 +1,22 @@
-use std::fs::File;
+use std::collections::HashSet;
+use std::fs::{self, File};
 use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
-use clap::{App, Arg, SubCommand};
+use std::{env, process};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
 use colored::*;
 use git2::{Repository};
 use regex::Regex;
+use serde_yaml::Value;
 
-use crate::clone::GitRepo;
-use crate::conf::{GGConf, SSHConfig};
-use crate::dir::DirectoryTreeOptions;
+use crate::clone::{GitRemote, GitRepo};
+use crate::conf::{self, GGConf, SSHConfig};
+use crate::dir::{DirectoryTreeOptions, RepoSource};
 use crate::input_args::InputArgs;
+use crate::platform::{self, GitPlatform, RepoFilter, RepoOwner};
 use crate::SSH_CONF;
 
 pub fn sub_command<'a, 'b>() -> App<'a, 'b> {
@@ -17,75 +24,1114 @@ pub fn sub_command<'a, 'b>() -> App<'a, 'b> {
         .arg(
             Arg::with_name("root_path")
                 .short("r")
-                .default_value(".")
                 .takes_value(true)
-                .help("path of the repo where branches are to be compared. Defaults to '.'"),
+                .multiple(true)
+                .number_of_values(1)
+                .help("path at which to look for the git repos. Can be passed multiple times. Defaults to the roots in the conf or '.'"),
         ).arg(
         Arg::with_name("traverse-hidden")
             .short("i")
             .help("traverse through hidden directories also"),
-    )
+    ).arg(
+        Arg::with_name("nested")
+            .long("nested")
+            .help("also find the repos nested in the worktree of other repos"),
+    ).arg(
+        Arg::with_name("from_platform")
+            .long("from-platform")
+            .takes_value(true)
+            .possible_values(&["github"])
+            .help("list repos from the given platform instead of scanning the local directories. Needs --org or --user"),
+    ).arg(
+        Arg::with_name("token")
+            .short("t")
+            .takes_value(true)
+            .help("the access token to list private repos from the platform. Defaults to GITHUB_TOKEN env variable"),
+    ).arg(
+        Arg::with_name("ssh")
+            .long("ssh")
+            .help("use the ssh url of the repos listed from the platform"),
+    ).arg(
+        Arg::with_name("path_template")
+            .long("path-template")
+            .takes_value(true)
+            .default_value("{name}")
+            .help("template of the localPath for repos listed from the platform. Supports {owner} and {name}"),
+    ).arg(
+        Arg::with_name("write")
+            .long("write")
+            .help("update the conf file with the changes. A backup of the conf file is saved with a .bak extension"),
+    ).arg(
+        Arg::with_name("prune")
+            .long("prune")
+            .requires("write")
+            .help("also remove the entries of repos which are not found anymore"),
+    ).args(&platform::list_args())
+        .subcommand(
+            SubCommand::with_name("validate")
+                .about("check the conf file for errors and unknown keys"),
+        )
+        .subcommand(
+            SubCommand::with_name("show")
+                .about("print the effective conf, merged from the global conf, the workspace conf and env variables"),
+        )
+}
+
+pub fn show(conf_file: &str) {
+    let (effective, merged, sources) = conf::effective_conf(conf_file).unwrap_or_else(|err| {
+        println!("{} {}", "error while reading conf file:".red(), err.to_string().red());
+        process::exit(1);
+    });
+
+    let global_conf_file = conf::global_conf_file().map_or("-".to_string(), |file| file.to_string_lossy().to_string());
+    println!("{} {}", "Global conf:".green(), global_conf_file);
+    println!("{} {}", "Workspace conf:".green(), conf_file);
+    let mut merged_values = vec![];
+    flatten_value(&merged, String::new(), &mut merged_values);
+    let merged_paths: HashSet<String> = merged_values.into_iter().map(|(path, _)| path).collect();
+
+    let mut values = vec![];
+    flatten_value(&effective, String::new(), &mut values);
+    values.iter().for_each(|(path, value)| {
+        let source = conf::merged_conf_path(path)
+            .filter(|merged_path| merged_paths.contains(merged_path))
+            .and_then(|merged_path| conf::conf_source(&sources, &merged_path))
+            .unwrap_or("default");
+        println!("{}: {} {}", path, value, format!("({})", source).blue());
+    });
+}
+
+fn flatten_value(value: &Value, path: String, values: &mut Vec<(String, String)>) {
+    match value {
+        Value::Mapping(mapping) if !mapping.is_empty() => mapping.iter().for_each(|(key, value)| {
+            let key = key.as_str().unwrap_or_default();
+            let key_path = if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) };
+            flatten_value(value, key_path, values);
+        }),
+        Value::Sequence(sequence) if !sequence.is_empty() => sequence
+            .iter()
+            .enumerate()
+            .for_each(|(index, value)| flatten_value(value, format!("{}[{}]", path, index), values)),
+        Value::Mapping(_) => values.push((path, "{}".to_string())),
+        Value::Sequence(_) => values.push((path, "[]".to_string())),
+        Value::String(string) => values.push((path, string.clone())),
+        Value::Null => values.push((path, "~".to_string())),
+        Value::Bool(boolean) => values.push((path, boolean.to_string())),
+        Value::Number(number) => values.push((path, number.to_string())),
+    }
+}
+
+pub fn validate(conf_file: &str) {
+    let content = fs::read_to_string(conf_file).unwrap_or_else(|err| {
+        println!("{} {} {}", "Failed to read the conf file".red(), conf_file.red(), err.to_string().red());
+        process::exit(1);
+    });
+    let conf_dir = Path::new(conf_file).parent().unwrap_or_else(|| Path::new(""));
+
+    let issues = conf::validate_conf(&content, conf_dir);
+    issues.iter().for_each(|issue| {
+        let severity = if issue.is_error { "error".red() } else { "warning".yellow() };
+        println!("{}:{}: {}: {}", conf_file, issue.line, severity, issue.message);
+    });
+
+    let errors = issues.iter().filter(|issue| issue.is_error).count();
+    if errors > 0 {
+        println!("{} {}", errors.to_string().red(), "error(s) found".red());
+        process::exit(1);
+    }
+    println!("{} {}", conf_file.green(), "is valid".green());
 }
 
-pub fn config(args: InputArgs, filter_list_regex: Vec<Regex>, filter_list: Vec<String>,
-              mut existing_clone_repos: Vec<GitRepo>) {
-    let root_path = args.get_root_path("root_path");
-    let root = root_path
-        .to_str()
-        .expect(format!("{}", "Error in converting directory to string".red()).as_str());
+pub fn config(args: InputArgs, conf_file: &str, dir_options: DirectoryTreeOptions) {
     let matches = args.get_matches();
     let filter_hidden = matches.is_present("traverse-hidden");
 
     let dir_tree_with_options = DirectoryTreeOptions {
-        filter_list: filter_list_regex.clone(),
         filter_hidden,
+        nested: matches.is_present("nested"),
+        // The conf is generated from the repos on disk.
+        source: RepoSource::Disk,
+        ..dir_options
     };
+    let roots = args.get_root_paths("root_path", &dir_tree_with_options.roots);
+
+    // Only the workspace conf file is compared and updated, not the global conf merged into it.
+    let workspace_conf = conf::read_conf_file(conf_file).unwrap_or_else(|err| {
+        println!("{} {}", "error while reading conf file:".red(), err.to_string().red());
+        process::exit(1);
+    });
+    let existing_clone_repos = workspace_conf.clone_repos;
+
+    let from_platform = matches.value_of("from_platform");
+    let mut git_repos = match from_platform {
+        Some(platform) => platform_repos(matches, GitPlatform::from_str(platform), &existing_clone_repos),
+        None => local_repos(dir_tree_with_options, &roots),
+    };
+
+    let conf_dir = match Path::new(conf_file).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    // Removed repos are the ones in the conf file, under the scanned directories, which are not found anymore.
+    // Repos listed from a platform are not compared against the local directories.
+    let removed: Vec<usize> = match from_platform {
+        Some(_) => vec![],
+        None => {
+            let scanned_paths: Vec<PathBuf> = git_repos.iter().map(|repo| absolute_path(Path::new(&repo.local_path))).collect();
+            let root_dirs: Vec<PathBuf> = roots.iter().map(|root| absolute_path(root)).collect();
+            existing_clone_repos
+                .iter()
+                .enumerate()
+                .filter(|(_, repo)| {
+                    let path = absolute_path(Path::new(&repo.local_path));
+                    root_dirs.iter().any(|root_dir| path.starts_with(root_dir)) && !scanned_paths.contains(&path)
+                })
+                .map(|(index, _)| index)
+                .collect()
+        }
+    };
+
+    // Paths are written relative to the conf file, so that it can be used on other machines too.
+    git_repos.iter_mut().for_each(|repo| {
+        repo.local_path = relative_path(Path::new(&repo.local_path), &conf_dir)
+            .to_string_lossy()
+            .to_string();
+    });
+    let existing_paths: Vec<String> = existing_clone_repos
+        .iter()
+        .map(|repo| relative_path(Path::new(&repo.local_path), &conf_dir).to_string_lossy().to_string())
+        .collect();
+
+    // Entries whose repo is not found at their path anymore can be matched to a scanned repo by remote url,
+    // as the repo may have been moved.
+    let missing: Vec<usize> = existing_clone_repos
+        .iter()
+        .enumerate()
+        .filter(|(index, repo)| removed.contains(index) || !absolute_path(Path::new(&repo.local_path)).exists())
+        .map(|(index, _)| index)
+        .collect();
+
+    let diff = ConfDiff::new(&existing_clone_repos, &existing_paths, git_repos, removed, &missing);
+    diff.print(&existing_clone_repos, &existing_paths);
+    if diff.is_empty() && Path::new(conf_file).exists() {
+        return;
+    }
+
+    if !matches.is_present("write") {
+        println!("{} {}", "Pass --write to update the conf file at".yellow(), conf_file.yellow());
+        return;
+    }
+
+    if !Path::new(conf_file).exists() {
+        let ssh_conf = SSH_CONF.lock().unwrap();
+
+        let config = SSHConfig {
+            private_key: ssh_conf.private_key.clone(),
+            username: ssh_conf.username.clone(),
+            ssh_agent: ssh_conf.ssh_agent,
+        };
+
+        let new_conf = GGConf {
+            filter_list: workspace_conf.filter_list,
+            roots: workspace_conf.roots,
+            source: workspace_conf.source,
+            respect_gitignore: workspace_conf.respect_gitignore,
+            clone_repos: diff.added,
+            ssh_config: Some(config),
+            create_options: workspace_conf.create_options,
+        };
+
+        let yaml_string = serde_yaml::to_string(&new_conf).expect("Failed to parse yaml string from conf object");
+        let mut file = File::create(conf_file).expect("Failed to create new conf file");
+        file.write_all(yaml_string.as_bytes()).expect("failed to write ggConf content to new conf file");
+        println!("{} {}", "Yaml saved at:".green(), conf_file.blue());
+        return;
+    }
+
+    let prune = matches.is_present("prune");
+    let updated = fs::read_to_string(conf_file)
+        .map_err(|err| err.to_string())
+        .and_then(|content| diff.apply(&content, existing_clone_repos.len(), prune))
+        .and_then(|content| write_atomically(conf_file, &content).map_err(|err| err.to_string()));
+    match updated {
+        Ok(backup) => println!("{} {} {} {}", "Updated".green(), conf_file.blue(), "Backup saved at:".green(), backup.blue()),
+        Err(err) => {
+            println!("{} {}", "Failed to update the conf file:".red(), err.red());
+            process::exit(1);
+        }
+    }
+}
+
+// The original file is backed up before being replaced. The new content is written to a temporary file
+// in the same directory first, so that the conf file is never left half written.
+fn write_atomically(conf_file: &str, content: &str) -> Result<String, std::io::Error> {
+    let backup = format!("{}.bak", conf_file);
+    let temporary = format!("{}.tmp", conf_file);
+    fs::copy(conf_file, &backup)?;
+    let mut file = File::create(&temporary)?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&temporary, conf_file)?;
+    Ok(backup)
+}
+
+// Entries are matched by their local path. An entry at the same path with another remote url or other remotes is changed.
+// A repo not found at any path is matched by its remote url to a missing entry, whose path is then changed.
+struct ConfDiff {
+    added: Vec<GitRepo>,
+    changed: Vec<EntryChange>,
+    removed: Vec<usize>,
+}
+
+// The index of the existing entry and its new values, if any.
+struct EntryChange {
+    index: usize,
+    local_path: Option<String>,
+    remote_url: Option<String>,
+    remotes: Option<Vec<GitRemote>>,
+}
+
+impl ConfDiff {
+    fn new(existing: &[GitRepo], existing_paths: &[String], scanned: Vec<GitRepo>, mut removed: Vec<usize>, missing: &[usize]) -> ConfDiff {
+        let mut added = vec![];
+        let mut changed = vec![];
+        let mut unmatched = vec![];
+        for repo in scanned {
+            match existing_paths.iter().position(|path| *path == repo.local_path) {
+                Some(index) => {
+                    let remote_url = Some(repo.remote_url).filter(|remote_url| *remote_url != existing[index].remote_url);
+                    let remotes = changed_remotes(&existing[index], repo.remotes);
+                    if remote_url.is_some() || remotes.is_some() {
+                        changed.push(EntryChange { index, local_path: None, remote_url, remotes });
+                    }
+                }
+                None => unmatched.push(repo),
+            }
+        }
+
+        let mut moved: Vec<usize> = vec![];
+        for repo in unmatched {
+            let slug = repo_slug(&repo.remote_url);
+            let moved_from = missing
+                .iter()
+                .cloned()
+                .find(|index| !moved.contains(index) && repo_slug(&existing[*index].remote_url) == slug);
+            match moved_from {
+                Some(index) => {
+                    moved.push(index);
+                    removed.retain(|removed_index| *removed_index != index);
+                    changed.push(EntryChange {
+                        index,
+                        local_path: Some(repo.local_path),
+                        remote_url: Some(repo.remote_url).filter(|remote_url| *remote_url != existing[index].remote_url),
+                        remotes: changed_remotes(&existing[index], repo.remotes),
+                    });
+                }
+                None => added.push(repo),
+            }
+        }
+        ConfDiff { added, changed, removed }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+
+    fn print(&self, existing: &[GitRepo], existing_paths: &[String]) {
+        if self.is_empty() {
+            println!("{}", "The conf file is up to date".green());
+            return;
+        }
+        self.added.iter().for_each(|repo| {
+            println!("{} {} {}", "+".green(), repo.local_path.green(), repo.remote_url)
+        });
+        self.changed.iter().for_each(|change| {
+            let path = match &change.local_path {
+                Some(local_path) => format!("{} -> {}", existing_paths[change.index], local_path),
+                None => existing_paths[change.index].clone(),
+            };
+            let remote_url = match &change.remote_url {
+                Some(remote_url) => format!("{} -> {}", existing[change.index].remote_url, remote_url),
+                None => existing[change.index].remote_url.clone(),
+            };
+            match &change.remotes {
+                Some(remotes) => println!(
+                    "{} {} {} remotes: {} -> {}",
+                    "~".yellow(),
+                    path.yellow(),
+                    remote_url,
+                    remote_names(&existing[change.index].remotes),
+                    remote_names(remotes)
+                ),
+                None => println!("{} {} {}", "~".yellow(), path.yellow(), remote_url),
+            }
+        });
+        self.removed.iter().for_each(|index| {
+            println!("{} {} {}", "-".red(), existing_paths[*index].red(), existing[*index].remote_url)
+        });
+    }
 
+    // Edits the text of the conf file instead of serializing the conf, so that comments, formatting
+    // and unknown keys are preserved. Removed entries are only deleted when pruning.
+    fn apply(&self, content: &str, existing_count: usize, prune: bool) -> Result<String, String> {
+        let mut lines: Vec<String> = content.lines().map(String::from).collect();
+        // An empty flow sequence, as written for a conf without repos, is treated like an empty block.
+        let clone_repos_key = Regex::new(r"^cloneRepos:\s*(\[\s*\]\s*)?(#.*)?$").expect("failed to construct regex");
+        let remote_url_key = Regex::new(r"^(\s*(?:-\s+)?remoteURL:\s*)").expect("failed to construct regex");
+        let local_path_key = Regex::new(r"^(\s*(?:-\s+)?localPath:\s*)").expect("failed to construct regex");
+
+        let key_line = match lines.iter().position(|line| clone_repos_key.is_match(line)) {
+            Some(key_line) => {
+                let captures = clone_repos_key.captures(&lines[key_line]).unwrap();
+                if captures.get(1).is_some() && !self.added.is_empty() {
+                    let comment = captures.get(2).map_or(String::new(), |comment| format!(" {}", comment.as_str()));
+                    lines[key_line] = format!("cloneRepos:{}", comment);
+                }
+                key_line
+            }
+            None => {
+                if existing_count > 0 || lines.iter().any(|line| line.starts_with("cloneRepos:")) {
+                    return Err("cloneRepos is not a block sequence and cannot be updated in place".to_string());
+                }
+                lines.push("cloneRepos:".to_string());
+                lines.len() - 1
+            }
+        };
+
+        // The block ends at the next top level key.
+        let block_end = lines
+            .iter()
+            .enumerate()
+            .skip(key_line + 1)
+            .find(|(_, line)| !line.trim().is_empty() && !line.starts_with(' ') && !line.starts_with('#') && !line.starts_with('-'))
+            .map_or(lines.len(), |(index, _)| index);
+
+        let item_indent = lines[key_line + 1..block_end]
+            .iter()
+            .find(|line| line.trim_start().starts_with('-'))
+            .map(|line| line.len() - line.trim_start().len());
+        let mut items: Vec<usize> = match item_indent {
+            Some(indent) => (key_line + 1..block_end)
+                .filter(|index| {
+                    let line = &lines[*index];
+                    line.len() - line.trim_start().len() == indent && line.trim_start().starts_with('-')
+                })
+                .collect(),
+            None => vec![],
+        };
+        if items.len() != existing_count {
+            return Err(format!("found {} entries in cloneRepos instead of {}", items.len(), existing_count));
+        }
+        let item_end = |item: usize| items.get(item + 1).cloned().unwrap_or(block_end);
+
+        // Values on a single line are replaced first, as that keeps the lines of the entries where they are.
+        for change in self.changed.iter() {
+            let updates = [(&remote_url_key, &change.remote_url, "remoteURL"), (&local_path_key, &change.local_path, "localPath")];
+            for (key, value, name) in updates.iter() {
+                let value = match value {
+                    Some(value) => value,
+                    None => continue,
+                };
+                let item = change.index;
+                let line = (items[item]..item_end(item))
+                    .find(|index| key.is_match(&lines[*index]))
+                    .ok_or_else(|| format!("{} not found for entry {}", name, item + 1))?;
+                let prefix = key.find(&lines[line]).unwrap().as_str().to_string();
+                lines[line] = format!("{}{}", prefix, quote(value));
+            }
+        }
+
+        // Rewriting remotes and pruning add and remove lines. So the entries are edited from the last one,
+        // which leaves the lines of the entries before it where they are.
+        let mut block_end = block_end;
+        let mut edited: Vec<usize> = self
+            .changed
+            .iter()
+            .filter(|change| change.remotes.is_some())
+            .map(|change| change.index)
+            .collect();
+        if prune {
+            edited.extend(self.removed.iter().cloned());
+        }
+        edited.sort();
+        edited.dedup();
+        for item in edited.into_iter().rev() {
+            let end = items.get(item + 1).cloned().unwrap_or(block_end);
+            if prune && self.removed.contains(&item) {
+                // Comments right above an entry belong to it. Blank lines and comments after it are kept,
+                // as they separate it from or belong to what follows.
+                let mut start = items[item];
+                while start > key_line + 1 && is_comment(&lines[start - 1]) {
+                    start -= 1;
+                }
+                let mut end = end;
+                while end > items[item] + 1 && (lines[end - 1].trim().is_empty() || is_comment(&lines[end - 1])) {
+                    end -= 1;
+                }
+                let range = start..end;
+                block_end -= range.len();
+                lines.drain(range);
+                // The entry before now ends where this one started.
+                items[item] = start;
+                continue;
+            }
+            if let Some(change) = self.changed.iter().find(|change| change.index == item) {
+                if let Some(remotes) = &change.remotes {
+                    let (range, remote_lines) = remotes_lines(&lines, items[item], end, remotes)
+                        .map_err(|err| format!("{} for entry {}", err, item + 1))?;
+                    block_end = block_end + remote_lines.len() - range.len();
+                    lines.splice(range, remote_lines);
+                }
+            }
+        }
+
+        let indent = " ".repeat(item_indent.unwrap_or(2));
+        let mut added_lines: Vec<String> = vec![];
+        for repo in self.added.iter() {
+            let yaml = serde_yaml::to_string(&vec![repo]).map_err(|err| err.to_string())?;
+            yaml.lines()
+                .filter(|line| *line != "---")
+                .for_each(|line| added_lines.push(format!("{}{}", indent, line)));
+        }
+
+        // Added entries go after the last entry, before any trailing blank lines or comments of the block.
+        let insert_at = (key_line..block_end)
+            .rev()
+            .find(|index| !lines[*index].trim().is_empty() && !is_comment(&lines[*index]))
+            .map_or(block_end, |index| index + 1);
+        lines.splice(insert_at..insert_at, added_lines);
+
+        let mut updated = lines.join("\n");
+        updated.push('\n');
+        Ok(updated)
+    }
+}
+
+// The remotes are compared regardless of their order. Repos listed from a platform have no remotes to compare.
+fn changed_remotes(existing: &GitRepo, remotes: Vec<GitRemote>) -> Option<Vec<GitRemote>> {
+    let sorted = |remotes: &[GitRemote]| {
+        let mut remotes = remotes.to_vec();
+        remotes.sort_by(|first, second| first.name.cmp(&second.name));
+        remotes
+    };
+    if remotes.is_empty() || sorted(&existing.remotes) == sorted(&remotes) {
+        return None;
+    }
+    Some(remotes)
+}
+
+fn remote_names(remotes: &[GitRemote]) -> String {
+    if remotes.is_empty() {
+        return "none".to_string();
+    }
+    remotes.iter().map(|remote| remote.name.as_str()).collect::<Vec<_>>().join(", ")
+}
+
+// The lines of the entry between start and end to replace, with the lines of its new remotes.
+// An existing remotes key is replaced along with its sequence, else the remotes go after the last key of the entry.
+fn remotes_lines(lines: &[String], start: usize, end: usize, remotes: &[GitRemote]) -> Result<(Range<usize>, Vec<String>), String> {
+    let block_key = Regex::new(r"^(\s*(?:-\s+)?)\w+:").expect("failed to construct regex");
+    let remotes_key = Regex::new(r"^(\s*(?:-\s+)?)remotes:").expect("failed to construct regex");
+    let key_column = (start..end)
+        .find_map(|index| block_key.captures(&lines[index]).map(|captures| captures[1].len()))
+        .ok_or_else(|| "remotes cannot be updated in a flow style entry".to_string())?;
+
+    let mut content_end = end;
+    while content_end > start + 1 && (lines[content_end - 1].trim().is_empty() || is_comment(&lines[content_end - 1])) {
+        content_end -= 1;
+    }
+
+    let (range, prefix) = match (start..content_end).find(|index| remotes_key.is_match(&lines[*index])) {
+        Some(key_line) => {
+            // The sequence is indented under the key, or starts at the column of the key.
+            let mut sequence_end = key_line + 1;
+            while sequence_end < content_end && {
+                let line = &lines[sequence_end];
+                let indent = line.len() - line.trim_start().len();
+                line.trim().is_empty() || indent > key_column || (indent == key_column && line.trim_start().starts_with('-'))
+            } {
+                sequence_end += 1;
+            }
+            while sequence_end > key_line + 1 && lines[sequence_end - 1].trim().is_empty() {
+                sequence_end -= 1;
+            }
+            let prefix = remotes_key.captures(&lines[key_line]).unwrap()[1].to_string();
+            (key_line..sequence_end, prefix)
+        }
+        None => (content_end..content_end, " ".repeat(key_column)),
+    };
+
+    let yaml = serde_yaml::to_string(remotes).map_err(|err| err.to_string())?;
+    let indent = " ".repeat(key_column + 2);
+    let mut remote_lines = vec![format!("{}remotes:", prefix)];
+    yaml.lines()
+        .filter(|line| *line != "---")
+        .for_each(|line| remote_lines.push(format!("{}{}", indent, line)));
+    Ok((range, remote_lines))
+}
+
+fn is_comment(line: &str) -> bool {
+    line.trim_start().starts_with('#')
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn absolute_path(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| {
+        env::current_dir()
+            .map(|current_dir| current_dir.join(path))
+            .unwrap_or_else(|_| path.to_path_buf())
+    })
+}
+
+// Both paths are made absolute before comparing them. Paths outside of the base are reached through `..`.
+fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let path = absolute_path(path);
+    let base = absolute_path(base);
+
+    let common = path
+        .components()
+        .zip(base.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut relative = PathBuf::new();
+    base.components().skip(common).for_each(|_| relative.push(".."));
+    path.components().skip(common).for_each(|component| relative.push(component));
+
+    if relative.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        relative
+    }
+}
+
+fn local_repos(dir_tree_with_options: DirectoryTreeOptions, roots: &[PathBuf]) -> Vec<GitRepo> {
     let mut git_repos: Vec<GitRepo> = Vec::new();
     dir_tree_with_options
-        .process_directories(root)
-        .flat_map(|dir| {
-            dir.ok().and_then(|d| {
-                if d.file_name().eq(".git") {
-                    d.path().parent().map(|e| e.to_path_buf())
-                } else {
-                    None
+        .find_repos(roots)
+        .filter_map(|dir| {
+            let local_path = dir.clone().to_str().expect("Failed to extract string from path").to_string();
+            let repo = match Repository::open(dir) {
+                Ok(repo) => repo,
+                Err(err) => {
+                    println!("{} {}: {}", "Skipping".yellow(), local_path.yellow(), err.message());
+                    return None;
                 }
+            };
+
+            let remotes = repo_remotes(&repo);
+            let remote_url = match remotes.iter().find(|remote| remote.name == "origin") {
+                Some(origin) => origin.url.clone(),
+                None => match remotes.first() {
+                    Some(remote) => {
+                        println!("{} {}{} {} {}", "No origin found for".yellow(), local_path.yellow(),
+                                 ".".yellow(), "Using the remote".yellow(), remote.name.yellow());
+                        remote.url.clone()
+                    }
+                    None => {
+                        println!("{} {} {}", "Skipping".yellow(), local_path.yellow(), "as it has no remotes".yellow());
+                        return None;
+                    }
+                },
+            };
+
+            Some(GitRepo {
+                remote_url,
+                local_path,
+                remotes,
+                ..Default::default()
+            })
+        }).for_each(|repo| git_repos.push(repo));
+    git_repos
+}
+
+// Remotes without a valid url are left out.
+fn repo_remotes(repo: &Repository) -> Vec<GitRemote> {
+    let remote_names = match repo.remotes() {
+        Ok(remote_names) => remote_names,
+        Err(_) => return vec![],
+    };
+    remote_names
+        .iter()
+        .flatten()
+        .filter_map(|name| {
+            let remote = repo.find_remote(name).ok()?;
+            let url = remote.url()?;
+            Some(GitRemote {
+                name: name.to_string(),
+                url: url.to_string(),
             })
         })
-        .map(|dir| {
-            let local_path = dir.clone().to_str().expect("Failed to extract string from path").to_string();
-            let repo = Repository::open(dir).expect("Failed to open git repo");
-            let remote = repo.find_remote("origin").expect("Failed to get remote with name origin in the repo");
-            let remote_url = remote.url();
+        .collect()
+}
 
+// Lists the repos of the owner from the platform and returns the ones missing in the existing conf.
+// Existing entries whose repo has been archived upstream are reported.
+fn platform_repos(matches: &ArgMatches, platform: GitPlatform, existing_clone_repos: &[GitRepo]) -> Vec<GitRepo> {
+    let owner = RepoOwner::from_matches(matches).unwrap_or_else(|| {
+        println!("{}", "Please pass --org or --user to list repos from the platform".red());
+        process::exit(1);
+    });
+    let owner_name = match owner {
+        RepoOwner::Org(name) | RepoOwner::User(name) => name,
+    };
+    let token = matches
+        .value_of("token")
+        .map(String::from)
+        .or_else(|| env::var("GITHUB_TOKEN").ok())
+        .unwrap_or_default();
+    let listed_repos = platform::list_repos(platform, &owner, &token).unwrap_or_else(|err| {
+        println!("{} {}", "Failed listing repos from the platform:".red(), err);
+        process::exit(err.exit_code());
+    });
+
+    let existing_slugs: Vec<String> = existing_clone_repos
+        .iter()
+        .map(|repo| repo_slug(&repo.remote_url))
+        .collect();
+
+    listed_repos
+        .iter()
+        .filter(|repo| repo.archived)
+        .for_each(|repo| {
+            existing_clone_repos
+                .iter()
+                .filter(|existing| repo_slug(&existing.remote_url) == repo_slug(&repo.clone_url))
+                .for_each(|existing| {
+                    println!("{} {}", "Archived upstream:".yellow(), existing.local_path)
+                })
+        });
+
+    let filter = RepoFilter::from_matches(matches);
+    let path_template = matches.value_of("path_template").unwrap();
+    let use_ssh = matches.is_present("ssh");
+    listed_repos
+        .into_iter()
+        .filter(|repo| filter.is_match(repo))
+        .filter(|repo| !existing_slugs.contains(&repo_slug(&repo.clone_url)))
+        .map(|repo| {
+            println!("{} {}", "New upstream:".green(), repo.name);
             GitRepo {
-                remote_url: remote_url.expect("Failed to get remote url as string").to_string(),
-                local_path: local_path,
+                local_path: path_template.replace("{owner}", owner_name).replace("{name}", &repo.name),
+                remote_url: if use_ssh { repo.ssh_url } else { repo.clone_url },
+                ..Default::default()
             }
-        }).for_each(|repo| git_repos.push(repo));
+        })
+        .collect()
+}
 
-    git_repos.append(&mut existing_clone_repos);
+// Reduces https and ssh remote urls of the same repo to `owner/name`, so that they can be compared.
+fn repo_slug(remote_url: &str) -> String {
+    let path = remote_url.trim_end_matches('/').trim_end_matches(".git").replace(':', "/");
+    let mut segments = path.rsplit('/');
+    let name = segments.next().unwrap_or_default();
+    let owner = segments.next().unwrap_or_default();
+    format!("{}/{}", owner, name).to_lowercase()
+}
 
-    let ssh_conf = SSH_CONF.lock().unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let config = SSHConfig {
-        private_key: ssh_conf.private_key.clone(),
-        username: ssh_conf.username.clone(),
-        ssh_agent: ssh_conf.ssh_agent,
-    };
+    fn repo(remote_url: &str, local_path: &str) -> GitRepo {
+        GitRepo {
+            remote_url: remote_url.to_string(),
+            local_path: local_path.to_string(),
+            ..Default::default()
+        }
+    }
 
-    let new_conf = GGConf {
-        filter_list: filter_list,
-        filter_list_regex: filter_list_regex,
-        clone_repos: git_repos,
-        ssh_config: Some(config),
-    };
+    fn diff(added: Vec<GitRepo>, changed: Vec<EntryChange>, removed: Vec<usize>) -> ConfDiff {
+        ConfDiff { added, changed, removed }
+    }
+
+    fn remote_url_change(index: usize, remote_url: &str) -> EntryChange {
+        EntryChange {
+            index,
+            local_path: None,
+            remote_url: Some(remote_url.to_string()),
+            remotes: None,
+        }
+    }
+
+    fn remote(name: &str, url: &str) -> GitRemote {
+        GitRemote {
+            name: name.to_string(),
+            url: url.to_string(),
+        }
+    }
+
+    fn repo_with_remotes(local_path: &str, remotes: Vec<GitRemote>) -> GitRepo {
+        GitRepo {
+            remotes,
+            ..repo("https://github.com/o/a.git", local_path)
+        }
+    }
+
+    #[test]
+    fn should_match_entries_by_local_path() {
+        let existing = vec![repo("https://github.com/o/a.git", "a"), repo("https://github.com/o/b.git", "b")];
+        let paths = vec!["a".to_string(), "b".to_string()];
+        let scanned = vec![repo("https://github.com/o/a.git", "a"), repo("https://github.com/o/new-b.git", "b"), repo("https://github.com/o/c.git", "c")];
+
+        let diff = ConfDiff::new(&existing, &paths, scanned, vec![], &[]);
+
+        assert_eq!(diff.added.iter().map(|repo| repo.local_path.as_str()).collect::<Vec<_>>(), vec!["c"]);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].index, 1);
+        assert_eq!(diff.changed[0].local_path, None);
+        assert_eq!(diff.changed[0].remote_url.as_deref(), Some("https://github.com/o/new-b.git"));
+    }
+
+    #[test]
+    fn should_change_entries_whose_remotes_changed() {
+        let origin = remote("origin", "https://github.com/o/a.git");
+        let fork = remote("fork", "https://github.com/me/a.git");
+        let existing = vec![repo_with_remotes("a", vec![origin.clone()]), repo_with_remotes("b", vec![origin.clone(), fork.clone()])];
+        let paths = vec!["a".to_string(), "b".to_string()];
+        let scanned = vec![repo_with_remotes("a", vec![fork.clone(), origin.clone()]), repo_with_remotes("b", vec![origin.clone()])];
+
+        let diff = ConfDiff::new(&existing, &paths, scanned, vec![], &[]);
+
+        assert!(diff.added.is_empty());
+        assert_eq!(diff.changed.len(), 2);
+        assert_eq!(diff.changed[0].index, 0);
+        assert_eq!(diff.changed[0].remote_url, None);
+        assert_eq!(diff.changed[0].remotes, Some(vec![fork, origin.clone()]));
+        assert_eq!(diff.changed[1].remotes, Some(vec![origin]));
+    }
+
+    #[test]
+    fn should_ignore_the_order_of_remotes() {
+        let origin = remote("origin", "https://github.com/o/a.git");
+        let fork = remote("fork", "https://github.com/me/a.git");
+        let existing = vec![repo_with_remotes("a", vec![origin.clone(), fork.clone()])];
+        let paths = vec!["a".to_string()];
+
+        let reordered = ConfDiff::new(&existing, &paths, vec![repo_with_remotes("a", vec![fork, origin])], vec![], &[]);
+        let listed = ConfDiff::new(&existing, &paths, vec![repo_with_remotes("a", vec![])], vec![], &[]);
+
+        assert!(reordered.is_empty());
+        assert!(listed.is_empty());
+    }
+
+    #[test]
+    fn should_rewrite_the_remotes_of_changed_entries() {
+        let content = "\
+cloneRepos:
+  - remoteURL: https://github.com/o/a.git
+    localPath: a
+    remotes:
+      - name: origin
+        url: https://github.com/o/a.git
+    tags: [api]
+  - remoteURL: https://github.com/o/b.git
+    localPath: b # no remotes yet
+  # the c repo
+  - remoteURL: https://github.com/o/c.git
+    localPath: c
+ssh:
+  username: git
+";
+        let remotes = vec![remote("origin", "https://github.com/o/a.git"), remote("fork", "https://github.com/me/a.git")];
+        let change = |index| EntryChange {
+            index,
+            local_path: None,
+            remote_url: None,
+            remotes: Some(remotes.clone()),
+        };
+
+        let updated = diff(vec![], vec![change(0), change(1)], vec![2]).apply(content, 3, true).unwrap();
+
+        assert_eq!(updated, "\
+cloneRepos:
+  - remoteURL: https://github.com/o/a.git
+    localPath: a
+    remotes:
+      - name: origin
+        url: \"https://github.com/o/a.git\"
+      - name: fork
+        url: \"https://github.com/me/a.git\"
+    tags: [api]
+  - remoteURL: https://github.com/o/b.git
+    localPath: b # no remotes yet
+    remotes:
+      - name: origin
+        url: \"https://github.com/o/a.git\"
+      - name: fork
+        url: \"https://github.com/me/a.git\"
+ssh:
+  username: git
+");
+    }
+
+    #[test]
+    fn should_rewrite_remotes_of_indentless_sequences() {
+        let content = "\
+cloneRepos:
+- remoteURL: https://github.com/o/a.git
+  localPath: a
+  remotes:
+  - name: origin
+    url: https://github.com/o/a.git
+
+ssh:
+  username: git
+";
+        let change = EntryChange {
+            index: 0,
+            local_path: None,
+            remote_url: None,
+            remotes: Some(vec![remote("upstream", "https://github.com/u/a.git")]),
+        };
+
+        let updated = diff(vec![], vec![change], vec![]).apply(content, 1, false).unwrap();
+
+        assert_eq!(updated, "\
+cloneRepos:
+- remoteURL: https://github.com/o/a.git
+  localPath: a
+  remotes:
+    - name: upstream
+      url: \"https://github.com/u/a.git\"
+
+ssh:
+  username: git
+");
+    }
+
+    #[test]
+    fn should_match_moved_repos_by_remote_url() {
+        let existing = vec![repo("https://github.com/o/r1.git", "old/r1")];
+        let paths = vec!["old/r1".to_string()];
+        let scanned = vec![repo("git@github.com:o/r1.git", "r1")];
 
-    let yaml_string = serde_yaml::to_string(&new_conf).expect("Failed to parse yaml string from conf object");
-    println!("{}", yaml_string);
-    let new_path = format!("{}/.ggConf.new.yaml", root);
-    let mut file = File::create(new_path.clone()).expect("Failed to create new conf file");
-    file.write_all(yaml_string.as_bytes()).expect("failed to write ggConf content to new conf file");
-    println!("{} {}", "Yaml saved at:".green(), new_path.blue())
-}
\ No newline at end of file
+        let diff = ConfDiff::new(&existing, &paths, scanned, vec![0], &[0]);
+
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].local_path.as_deref(), Some("r1"));
+        assert_eq!(diff.changed[0].remote_url.as_deref(), Some("git@github.com:o/r1.git"));
+    }
+
+    #[test]
+    fn should_not_move_entries_which_are_still_found() {
+        let existing = vec![repo("https://github.com/o/r1.git", "r1")];
+        let paths = vec!["r1".to_string()];
+        let scanned = vec![repo("https://github.com/o/r1.git", "r1"), repo("https://github.com/o/r1.git", "copy/r1")];
+
+        let diff = ConfDiff::new(&existing, &paths, scanned, vec![], &[]);
+
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].local_path, "copy/r1");
+        assert!(diff.changed.is_empty());
+    }
+
+    #[test]
+    fn should_add_entries_to_an_indentless_sequence() {
+        let content = "\
+cloneRepos:
+- remoteURL: https://github.com/o/a.git
+  localPath: a
+ssh:
+  username: git
+";
+
+        let updated = diff(vec![repo("https://github.com/o/b.git", "b")], vec![], vec![])
+            .apply(content, 1, false)
+            .unwrap();
+
+        assert_eq!(updated, "\
+cloneRepos:
+- remoteURL: https://github.com/o/a.git
+  localPath: a
+- remoteURL: \"https://github.com/o/b.git\"
+  localPath: b
+ssh:
+  username: git
+");
+    }
+
+    #[test]
+    fn should_keep_comments_between_entries() {
+        let content = "\
+# repos of the team
+cloneRepos:
+  # first
+  - remoteURL: https://github.com/o/a.git
+    localPath: a
+
+  # second
+  - remoteURL: https://github.com/o/b.git
+    localPath: b # the b repo
+# ssh settings
+ssh:
+  username: git
+";
+
+        let updated = diff(
+            vec![repo("https://github.com/o/c.git", "c")],
+            vec![remote_url_change(1, "https://github.com/o/new-b.git")],
+            vec![0],
+        )
+        .apply(content, 2, true)
+        .unwrap();
+
+        assert_eq!(updated, "\
+# repos of the team
+cloneRepos:
+
+  # second
+  - remoteURL: \"https://github.com/o/new-b.git\"
+    localPath: b # the b repo
+  - remoteURL: \"https://github.com/o/c.git\"
+    localPath: c
+# ssh settings
+ssh:
+  username: git
+");
+    }
+
+    #[test]
+    fn should_update_the_local_path_of_moved_entries() {
+        let content = "\
+cloneRepos:
+  - remoteURL: https://github.com/o/r1.git
+    localPath: old/r1
+";
+        let change = EntryChange {
+            index: 0,
+            local_path: Some("r1".to_string()),
+            remote_url: None,
+            remotes: None,
+        };
+
+        let updated = diff(vec![], vec![change], vec![]).apply(content, 1, false).unwrap();
+
+        assert_eq!(updated, "\
+cloneRepos:
+  - remoteURL: https://github.com/o/r1.git
+    localPath: \"r1\"
+");
+    }
+
+    #[test]
+    fn should_count_and_prune_flow_style_entries() {
+        let content = "\
+cloneRepos:
+  - {remoteURL: https://github.com/o/a.git, localPath: a}
+  - remoteURL: https://github.com/o/b.git
+    localPath: b
+";
+
+        let updated = diff(vec![], vec![], vec![0]).apply(content, 2, true).unwrap();
+
+        assert_eq!(updated, "\
+cloneRepos:
+  - remoteURL: https://github.com/o/b.git
+    localPath: b
+");
+    }
+
+    #[test]
+    fn should_not_change_flow_style_entries() {
+        let content = "\
+cloneRepos:
+  - {remoteURL: https://github.com/o/a.git, localPath: a}
+";
+
+        let updated = diff(vec![], vec![remote_url_change(0, "https://github.com/o/new-a.git")], vec![]).apply(content, 1, false);
+
+        assert_eq!(updated, Err("remoteURL not found for entry 1".to_string()));
+    }
+
+    #[test]
+    fn should_not_update_a_flow_style_sequence() {
+        let content = "cloneRepos: [{remoteURL: https://github.com/o/a.git, localPath: a}]\n";
+
+        let updated = diff(vec![repo("https://github.com/o/b.git", "b")], vec![], vec![]).apply(content, 1, false);
+
+        assert!(updated.is_err());
+    }
+
+    #[test]
+    fn should_add_entries_to_an_empty_flow_sequence() {
+        let content = "cloneRepos: [] # none yet\nssh:\n  username: git\n";
+
+        let updated = diff(vec![repo("https://github.com/o/a.git", "a")], vec![], vec![])
+            .apply(content, 0, false)
+            .unwrap();
+
+        assert_eq!(updated, "\
+cloneRepos: # none yet
+  - remoteURL: \"https://github.com/o/a.git\"
+    localPath: a
+ssh:
+  username: git
+");
+    }
+
+    #[test]
+    fn should_keep_trailing_blank_lines_while_pruning() {
+        let content = "\
+cloneRepos:
+  - remoteURL: https://github.com/o/a.git
+    localPath: a
+  - remoteURL: https://github.com/o/b.git
+    localPath: b
+
+
+ssh:
+  username: git
+";
+
+        let updated = diff(vec![], vec![], vec![1]).apply(content, 2, true).unwrap();
+
+        assert_eq!(updated, "\
+cloneRepos:
+  - remoteURL: https://github.com/o/a.git
+    localPath: a
+
+
+ssh:
+  username: git
+");
+    }
+
+    #[test]
+    fn should_keep_removed_entries_without_prune() {
+        let content = "\
+cloneRepos:
+  - remoteURL: https://github.com/o/a.git
+    localPath: a
+";
+
+        let updated = diff(vec![], vec![], vec![0]).apply(content, 1, false).unwrap();
+
+        assert_eq!(updated, content);
+    }
+
+    #[test]
+    fn should_reject_a_conf_with_a_different_number_of_entries() {
+        let content = "\
+cloneRepos:
+  - remoteURL: https://github.com/o/a.git
+    localPath: a
+";
+
+        let updated = diff(vec![], vec![], vec![]).apply(content, 2, false);
+
+        assert_eq!(updated, Err("found 1 entries in cloneRepos instead of 2".to_string()));
+    }
+
+    #[test]
+    fn should_reduce_remote_urls_to_their_slug() {
+        assert_eq!(repo_slug("https://github.com/Owner/Repo.git"), "owner/repo");
+        assert_eq!(repo_slug("git@github.com:owner/repo.git"), "owner/repo");
+        assert_eq!(repo_slug("ssh://git@github.com/owner/repo/"), "owner/repo");
+    }
+}
@@ -1,21 +1,25 @@
-use std::collections::HashMap;
-use std::error::Error;
-use std::{env, process};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{env, process, thread};
 
-use clap::{App, Arg, SubCommand};
+use clap::{App, Arg, ArgMatches, SubCommand};
 use colored::*;
-use reqwest::{Client, RequestBuilder};
+use git2::{BranchType, Cred, Error as GitError, PushOptions, RemoteCallbacks, Repository};
+use reqwest::RequestBuilder;
+use serde::{Deserialize, Serialize};
 
 use crate::clone::GitClone;
+use crate::git::GitAction;
 use crate::input_args::InputArgs;
-use crate::progress::ProgressTracker;
+use crate::platform::{self, GitPlatform, PlatformError};
+use crate::progress::{ProgressReporter, ProgressTracker};
 
 pub fn sub_command<'a, 'b>() -> App<'a, 'b> {
     SubCommand::with_name("create")
         .arg(
             Arg::with_name("repo_path")
                 .short("r")
-                .required(true)
+                .required_unless("fork")
                 .takes_value(true)
                 .help("path at which to create the local repo. Defaults to '.'"),
         )
@@ -29,16 +33,162 @@ pub fn sub_command<'a, 'b>() -> App<'a, 'b> {
         .arg(
             Arg::with_name("token")
                 .short("t")
+                .takes_value(true)
                 .help("the access token to create the repo remotely"),
         )
+        .arg(
+            Arg::with_name("private")
+                .long("private")
+                .conflicts_with("public")
+                .help("create the remote repo as private"),
+        )
+        .arg(
+            Arg::with_name("public")
+                .long("public")
+                .help("create the remote repo as public"),
+        )
+        .arg(
+            Arg::with_name("org")
+                .long("org")
+                .takes_value(true)
+                .help("the organization under which the remote repo is created. Defaults to the user account"),
+        )
+        .arg(
+            Arg::with_name("description")
+                .short("d")
+                .long("description")
+                .takes_value(true)
+                .help("a short description of the remote repo"),
+        )
+        .arg(
+            Arg::with_name("homepage")
+                .long("homepage")
+                .takes_value(true)
+                .help("the homepage url of the remote repo"),
+        )
+        .arg(
+            Arg::with_name("topic")
+                .long("topic")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("a topic to tag the remote repo with. Can be passed multiple times"),
+        )
+        .arg(
+            Arg::with_name("default_branch")
+                .long("default-branch")
+                .takes_value(true)
+                .help("the default branch of the remote repo"),
+        )
+        .arg(
+            Arg::with_name("auto_init")
+                .long("auto-init")
+                .help("initialize the remote repo with a README"),
+        )
+        .arg(
+            Arg::with_name("gitignore")
+                .long("gitignore")
+                .takes_value(true)
+                .help("the .gitignore template to initialize the remote repo with. Eg: Rust"),
+        )
+        .arg(
+            Arg::with_name("license")
+                .long("license")
+                .takes_value(true)
+                .help("the license template to initialize the remote repo with. Eg: mit"),
+        )
+        .arg(
+            Arg::with_name("template")
+                .long("template")
+                .takes_value(true)
+                .help("the template repository, as <owner>/<repo>, to generate the remote repo from"),
+        )
+        .arg(
+            Arg::with_name("fork")
+                .long("fork")
+                .takes_value(true)
+                .conflicts_with("template")
+                .help("the repository, as <owner>/<repo>, to fork. The fork is created under --org if given, else under the user account"),
+        )
 }
 
-pub fn create(args: InputArgs) {
+/// Options used while creating the remote repo.
+/// Defaults are read from the `create` section of the conf file and are overridden by flags.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct RepoOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub private: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub org: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub homepage: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub topics: Vec<String>,
+
+    #[serde(alias = "defaultBranch")]
+    #[serde(rename = "defaultBranch")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_branch: Option<String>,
+
+    #[serde(alias = "autoInit")]
+    #[serde(rename = "autoInit")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_init: Option<bool>,
+
+    #[serde(alias = "gitignoreTemplate")]
+    #[serde(rename = "gitignoreTemplate")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gitignore_template: Option<String>,
+
+    #[serde(alias = "licenseTemplate")]
+    #[serde(rename = "licenseTemplate")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license_template: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+}
+
+impl RepoOptions {
+    fn merge_args(mut self, matches: &ArgMatches) -> RepoOptions {
+        if matches.is_present("private") {
+            self.private = Some(true);
+        }
+        if matches.is_present("public") {
+            self.private = Some(false);
+        }
+        if matches.is_present("auto_init") {
+            self.auto_init = Some(true);
+        }
+        if let Some(topics) = matches.values_of("topic") {
+            self.topics = topics.map(String::from).collect();
+        }
+
+        let override_with = |field: &mut Option<String>, arg_name: &str| {
+            if let Some(value) = matches.value_of(arg_name) {
+                *field = Some(value.to_string());
+            }
+        };
+        override_with(&mut self.org, "org");
+        override_with(&mut self.description, "description");
+        override_with(&mut self.homepage, "homepage");
+        override_with(&mut self.default_branch, "default_branch");
+        override_with(&mut self.gitignore_template, "gitignore");
+        override_with(&mut self.license_template, "license");
+        override_with(&mut self.template, "template");
+        self
+    }
+}
+
+pub fn create(args: InputArgs, repo_options: RepoOptions) {
     let matches = args.get_matches();
-    let root_path = args.get_root_path("repo_path");
-    let repo_name = root_path
-        .to_str()
-        .expect(format!("{}", "Error in converting directory to string".red()).as_str());
 
     let mut token = String::from(matches.value_of("token").unwrap_or(""));
     if token == "" {
@@ -53,89 +203,369 @@ pub fn create(args: InputArgs) {
         }
     }
 
+    if let Some(source) = matches.value_of("fork") {
+        fork(&args, source, token, repo_options);
+        return;
+    }
+
+    let root_path = args.get_root_path("repo_path");
+    let repo_name = repo_name(&root_path).unwrap_or_else(|err| {
+        println!("{}", err.red());
+        process::exit(1);
+    });
+
     let platform = matches.value_of("platform").unwrap();
+    let options = repo_options.merge_args(matches);
 
-    let remote_url = create_remote(token, platform, repo_name).unwrap_or_else(|err| {
-        println!("{} {}", "Failed creating a remote repo:".red(), err);
+    // The local directory is checked before creating the remote repo, so that a failed push
+    // does not leave an empty repo behind on the platform.
+    let existing_project = is_existing_project(&root_path);
+    if existing_project && has_origin(&root_path) {
+        println!(
+            "{} {}",
+            "Remote named origin already exists in the local repo at".red(),
+            root_path.to_string_lossy().red()
+        );
         process::exit(1);
+    }
+
+    let remote_repo = GitRemoteRepo {
+        platform: GitPlatform::from_str(platform),
+        token: token.clone(),
+        repo_name,
+        options,
+    };
+    let (remote_url, full_name) = remote_repo.create().unwrap_or_else(|err| {
+        println!("{} {}", "Failed creating a remote repo:".red(), err);
+        process::exit(err.exit_code());
     });
 
-    let clone = GitClone {
-        remote_url,
-        local_path: root_path,
-        use_ssh: false,
+    let multi_bars = ProgressTracker::new(matches.value_of("jobs").and_then(|e| e.parse().ok()));
+    if existing_project {
+        println!("{}", "Local directory exists. Pushing it to the remote repo".blue());
+        multi_bars.start_task(GitInitPush {
+            remote_url,
+            local_path: root_path.clone(),
+            token,
+            default_branch: remote_repo.options.default_branch.clone(),
+        });
+    } else {
+        multi_bars.start_task(GitClone {
+            remote_url,
+            local_path: root_path.clone(),
+            use_ssh: false,
+            token: Some(token),
+        });
+    }
+    multi_bars.join().unwrap();
+
+    // An empty remote repo has no branches yet. So its default branch is only set once the branches are pushed.
+    if let (Some(branch), false) = (&remote_repo.options.default_branch, remote_repo.is_initialized()) {
+        let pushed = Repository::open(&root_path)
+            .map(|repo| repo.find_reference(&format!("refs/remotes/origin/{}", branch)).is_ok())
+            .unwrap_or(false);
+        if pushed {
+            remote_repo.set_default_branch(&full_name, branch);
+        } else {
+            println!("{} {}", "Default branch not set as it is not pushed:".yellow(), branch.yellow());
+        }
+    }
+}
+
+// The fork is cloned at -r if given, else at a directory named after the forked repo.
+fn fork(args: &InputArgs, source: &str, token: String, repo_options: RepoOptions) {
+    let matches = args.get_matches();
+    let root_path = match matches.value_of("repo_path") {
+        Some(_) => args.get_root_path("repo_path"),
+        None => PathBuf::from(source.rsplit('/').next().unwrap_or(source)),
+    };
+
+    let platform = matches.value_of("platform").unwrap();
+    let options = repo_options.merge_args(matches);
+    let remote_repo = GitRemoteRepo {
+        platform: GitPlatform::from_str(platform),
+        token: token.clone(),
+        repo_name: String::from(source),
+        options,
     };
 
+    let (remote_url, upstream_url) = remote_repo.fork().unwrap_or_else(|err| {
+        println!("{} {}", "Failed forking the remote repo:".red(), err);
+        process::exit(err.exit_code());
+    });
+
     let multi_bars = ProgressTracker::new(matches.value_of("jobs").and_then(|e| e.parse().ok()));
-    multi_bars.start_task(clone);
+    multi_bars.start_task(GitForkClone {
+        clone: GitClone {
+            remote_url,
+            local_path: root_path,
+            use_ssh: false,
+            token: Some(token),
+        },
+        upstream_url,
+    });
     multi_bars.join().unwrap();
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-enum GitPlatform {
-    Github,
+// Paths like `.` or `..` have no file name of their own. So existing directories are canonicalized first.
+fn repo_name(path: &Path) -> Result<String, String> {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(String::from)
+        .ok_or_else(|| format!("Could not work out a repo name from the path {}", path.to_string_lossy()))
 }
 
-impl GitPlatform {
-    fn from_str(platform: &str) -> GitPlatform {
-        match platform {
-            "github" => GitPlatform::Github,
-            _ => panic!("{}", "Unsupported platform".red()),
-        }
-    }
+// An empty directory can still be cloned into. So only non empty directories are treated as existing projects.
+fn is_existing_project(path: &Path) -> bool {
+    path.is_dir()
+        && path
+            .read_dir()
+            .map(|mut entries| entries.next().is_some())
+            .unwrap_or(false)
+}
 
-    fn create_api(&self, client: Client, repo_name: String, token: String) -> RequestBuilder {
-        match *self {
-            GitPlatform::Github => {
-                let mut data = HashMap::new();
-                data.insert("name", repo_name);
-                client
-                    .post("https:/api.github.com/user/repos")
-                    .header("Authorization", format!("token {}", token))
-                    .header("Accept", "application/vnd.github.v3+json")
-                    .header("Content-Type", "application/json")
-                    .json(&data)
-            }
-        }
-    }
+fn has_origin(path: &Path) -> bool {
+    Repository::open(path)
+        .map(|repo| repo.find_remote("origin").is_ok())
+        .unwrap_or(false)
 }
 
+// Waits up to a minute in total, doubling the delay from a second.
+const FORK_POLL_ATTEMPTS: u32 = 6;
+
 struct GitRemoteRepo {
     platform: GitPlatform,
     token: String,
     repo_name: String,
+    options: RepoOptions,
 }
 
 impl GitRemoteRepo {
-    fn create(self) -> Result<String, Box<dyn Error>> {
+    // Repos generated from a template or initialized with a file have a branch right after being created.
+    fn is_initialized(&self) -> bool {
+        self.options.auto_init == Some(true)
+            || self.options.template.is_some()
+            || self.options.gitignore_template.is_some()
+            || self.options.license_template.is_some()
+    }
+
+    // Returns the clone url and the full name of the created repo.
+    fn create(&self) -> Result<(String, String), PlatformError> {
         let client = reqwest::Client::new();
-        let request_builder = self.platform.create_api(client, self.repo_name, self.token);
-        let mut response = request_builder.send()?;
-        let returned_json: serde_json::Value = response.json()?;
-        let errors = returned_json["errors"]
-            .as_array()
-            .unwrap_or(&Vec::new())
-            .to_owned();
-        if errors.len() > 0 {
-            let message = errors[0]
-                .get("message")
-                .expect("Failed to get error message")
-                .to_owned();
-            println!("{} {:#?}", "Error message:".red(), message.to_string());
-            process::exit(1)
+        let request_builder = self.platform.create_api(&client, &self.repo_name, &self.token, &self.options);
+        let returned_json = platform::send(request_builder)?;
+        let (url, full_name) = match (returned_json["clone_url"].as_str(), returned_json["full_name"].as_str()) {
+            (Some(url), Some(full_name)) => (url, full_name),
+            _ => return Err(PlatformError::InvalidResponse("missing clone_url or full_name".to_string())),
+        };
+
+        if !self.options.topics.is_empty() {
+            let request = self.platform.topics_api(&client, full_name, &self.token, &self.options.topics);
+            self.update(request, "Failed to set topics:");
+        }
+
+        if let (Some(branch), true) = (&self.options.default_branch, self.is_initialized()) {
+            self.set_default_branch(full_name, branch);
+        }
+
+        Ok((url.to_owned(), full_name.to_owned()))
+    }
+
+    fn set_default_branch(&self, full_name: &str, branch: &str) {
+        let client = reqwest::Client::new();
+        let request = self.platform.default_branch_api(&client, full_name, &self.token, branch);
+        self.update(request, "Failed to set default branch:");
+    }
+
+    // Returns the clone url of the fork and of the repo it was forked from.
+    fn fork(self) -> Result<(String, String), PlatformError> {
+        let client = reqwest::Client::new();
+        let request_builder = self.platform.fork_api(&client, &self.repo_name, &self.token, self.options.org.as_ref());
+        let returned_json = platform::send(request_builder)?;
+        let (url, upstream_url, full_name) = match (
+            returned_json["clone_url"].as_str(),
+            returned_json["parent"]["clone_url"].as_str(),
+            returned_json["full_name"].as_str(),
+        ) {
+            (Some(url), Some(upstream_url), Some(full_name)) => (url, upstream_url, full_name),
+            _ => return Err(PlatformError::InvalidResponse("missing clone_url or full_name of the fork or its parent".to_string())),
+        };
+
+        self.wait_for_fork(&client, full_name);
+        Ok((url.to_owned(), upstream_url.to_owned()))
+    }
+
+    // Forks are created in the background, and cloning a fork before its commits are copied fails or clones an
+    // empty repo. The fork is polled with a backoff until it has commits. Forks of empty repos never do,
+    // so they are cloned anyway once the attempts run out.
+    fn wait_for_fork(&self, client: &reqwest::Client, full_name: &str) {
+        let mut delay = Duration::from_secs(1);
+        for attempt in 0..FORK_POLL_ATTEMPTS {
+            if platform::send(self.platform.latest_commit_api(client, full_name, &self.token)).is_ok() {
+                return;
+            }
+            if attempt == 0 {
+                println!("{} {}", "Waiting for the fork to be ready:".blue(), full_name);
+            }
+            thread::sleep(delay);
+            delay *= 2;
+        }
+        println!("{} {}", "The fork is not ready yet, cloning it anyway:".yellow(), full_name.yellow());
+    }
+
+    // The repo already exists once this is called. So failures are only reported.
+    fn update(&self, request: RequestBuilder, failure: &str) {
+        if let Err(err) = platform::send(request) {
+            println!("{} {}", failure.yellow(), err.to_string().yellow());
         }
-        let url = returned_json["clone_url"]
-            .as_str()
-            .expect("Failed to get remote url from response");
-        Ok(url.to_owned())
     }
 }
 
-fn create_remote(token: String, platform: &str, repo_name: &str) -> Result<String, Box<dyn Error>> {
-    let remote_repo = GitRemoteRepo {
-        platform: GitPlatform::from_str(platform),
-        token,
-        repo_name: String::from(repo_name),
-    };
-    remote_repo.create()
+/// Pushes an existing local directory to the newly created remote.
+/// The directory is initialized as a git repo if it is not one already.
+pub struct GitInitPush {
+    pub remote_url: String,
+    pub local_path: PathBuf,
+    pub token: String,
+    // The branch `origin/HEAD` points to, if pushed. Defaults to the checked out branch.
+    pub default_branch: Option<String>,
+}
+
+impl GitAction for GitInitPush {
+    fn get_name(&self) -> String {
+        self.remote_url.clone()
+    }
+
+    fn git_action(&mut self, prog: &ProgressReporter) -> Result<String, GitError> {
+        let repo = match Repository::open(&self.local_path) {
+            Ok(repo) => repo,
+            Err(_) => {
+                prog.report_message("Initializing git repo");
+                Repository::init(&self.local_path)?
+            }
+        };
+
+        if repo.find_remote("origin").is_ok() {
+            return Err(GitError::from_str("remote named origin already exists in the local repo"));
+        }
+        let mut remote = repo.remote("origin", &self.remote_url)?;
+
+        let mut branches = vec![];
+        for branch in repo.branches(Some(BranchType::Local))? {
+            let (branch, _) = branch?;
+            if let Some(name) = branch.name()? {
+                branches.push(name.to_string());
+            }
+        }
+
+        if branches.is_empty() {
+            return Ok(format!(
+                "{} {:#?}",
+                "Remote added as origin. No commits to push from".yellow(),
+                self.local_path.as_os_str()
+            ));
+        }
+
+        let refspecs: Vec<String> = branches
+            .iter()
+            .map(|branch| format!("refs/heads/{0}:refs/heads/{0}", branch))
+            .collect();
+
+        let token = self.token.as_str();
+        let mut callback = RemoteCallbacks::new();
+        callback.credentials(move |_url, _username, _allowed| Cred::userpass_plaintext(token, "x-oauth-basic"));
+        callback.push_update_reference(|reference, status| match status {
+            Some(message) => Err(GitError::from_str(format!("failed to push {}: {}", reference, message).as_str())),
+            None => Ok(()),
+        });
+
+        let mut push_options = PushOptions::new();
+        push_options.remote_callbacks(callback);
+
+        prog.report_message("Pushing branches");
+        let refspecs: Vec<&str> = refspecs.iter().map(|refspec| refspec.as_str()).collect();
+        remote.push(&refspecs, Some(&mut push_options))?;
+
+        for name in branches.iter() {
+            let mut branch = repo.find_branch(name, BranchType::Local)?;
+            branch.set_upstream(Some(format!("origin/{}", name).as_str()))?;
+        }
+
+        // Cloning sets `origin/HEAD` to the default branch of the remote, which the checked out branch is taken as here
+        // unless another one is set. Commands like `gg branches` rely on it to find the main branch.
+        let head_branch = repo.head().ok().and_then(|head| head.shorthand().map(String::from));
+        let default_branch = self
+            .default_branch
+            .clone()
+            .filter(|name| branches.contains(name))
+            .or_else(|| head_branch.filter(|name| branches.contains(name)))
+            .or_else(|| branches.first().cloned());
+        if let Some(default_branch) = default_branch {
+            let target = format!("refs/remotes/origin/{}", default_branch);
+            if repo.find_reference(&target).is_ok() {
+                repo.reference_symbolic("refs/remotes/origin/HEAD", &target, true, "gg create: set origin/HEAD")?;
+            }
+        }
+
+        Ok(format!(
+            "{} {}",
+            "Pushed local branches:".green(),
+            branches.join(", ")
+        ))
+    }
+}
+
+/// Clones a fork and adds the repo it was forked from as the `upstream` remote.
+pub struct GitForkClone {
+    pub clone: GitClone,
+    pub upstream_url: String,
+}
+
+impl GitAction for GitForkClone {
+    fn get_name(&self) -> String {
+        self.clone.get_name()
+    }
+
+    fn git_action(&mut self, prog: &ProgressReporter) -> Result<String, GitError> {
+        let cloned = self.clone.git_action(prog)?;
+        let repo = Repository::open(&self.clone.local_path)?;
+        repo.remote("upstream", &self.upstream_url)?;
+        Ok(format!(
+            "{} {} {}",
+            cloned,
+            "with upstream".green(),
+            self.upstream_url
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::current_dir;
+
+    #[test]
+    fn should_name_the_repo_after_the_current_directory_for_dot() {
+        let cwd = current_dir().unwrap();
+        let expected = cwd.file_name().unwrap().to_str().unwrap();
+        assert_eq!(repo_name(Path::new(".")), Ok(expected.to_string()));
+        assert_eq!(repo_name(Path::new("./")), Ok(expected.to_string()));
+    }
+
+    #[test]
+    fn should_ignore_a_trailing_slash() {
+        assert_eq!(repo_name(Path::new("src/")), Ok(String::from("src")));
+        assert_eq!(repo_name(Path::new("missing-dir/new-repo/")), Ok(String::from("new-repo")));
+    }
+
+    #[test]
+    fn should_name_the_repo_after_the_parent_for_dot_dot() {
+        assert_eq!(repo_name(Path::new("src/..")), repo_name(Path::new(".")));
+    }
+
+    #[test]
+    fn should_fail_when_the_path_has_no_name() {
+        assert!(repo_name(Path::new("/")).is_err());
+    }
 }
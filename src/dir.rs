@@ -1,11 +1,61 @@
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-use regex::Regex;
-use walkdir::{DirEntry, WalkDir};
+use colored::*;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use git2::Repository;
+use walkdir::{DirEntry, FilterEntry, IntoIter, WalkDir};
 
+use crate::index;
+
+pub const GG_IGNORE_FILE: &str = ".ggignore";
+
+#[derive(Clone)]
 pub struct DirectoryTreeOptions {
-    pub filter_list: Vec<Regex>,
+    pub filter_list: Vec<String>,
     pub filter_hidden: bool,
+    pub respect_gitignore: bool,
+    pub nested: bool,
+    pub max_depth: Option<usize>,
+    pub follow_links: bool,
+    // The roots to look into when none are passed to the command.
+    pub roots: Vec<PathBuf>,
+    // Looks into every directory again instead of only the ones changed since the repo index was saved.
+    pub rescan: bool,
+    pub source: RepoSource,
+    // The local paths of the repos listed in the conf.
+    pub conf_repos: Vec<PathBuf>,
+}
+
+// Where the repos a command acts on come from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RepoSource {
+    Conf,
+    Disk,
+    Both,
+}
+
+impl RepoSource {
+    pub fn from_str(source: &str) -> Option<RepoSource> {
+        match source {
+            "conf" => Some(RepoSource::Conf),
+            "disk" => Some(RepoSource::Disk),
+            "both" => Some(RepoSource::Both),
+            _ => None,
+        }
+    }
+}
+
+// Builds a matcher for the gitignore style patterns in skipDirectories. Patterns containing a `/` are
+// anchored to the root, `**` matches any number of directories and a leading `!` re-includes a directory.
+pub fn skip_matcher(root: &Path, patterns: &[String]) -> Result<Gitignore, ignore::Error> {
+    let mut builder = GitignoreBuilder::new(root);
+    for pattern in patterns {
+        builder.add_line(None, pattern)?;
+    }
+    builder.build()
 }
 
 impl DirectoryTreeOptions {
@@ -17,25 +67,64 @@ impl DirectoryTreeOptions {
             .unwrap_or(false)
     }
 
-    pub fn process_directories(self, path: &str) -> impl Iterator<Item=Result<DirEntry, walkdir::Error>> {
-        WalkDir::new(path)
-            .follow_links(false)
+    // Finds the git repos, linked worktrees, submodules and bare repos under the roots, using the repo index
+    // of each root, and or the repos listed in the conf. A repo found more than once, like under more than one
+    // root or through a symlink, is only returned once.
+    pub fn find_repos(self, roots: &[PathBuf]) -> impl Iterator<Item=PathBuf> {
+        let conf_repos = match self.source {
+            RepoSource::Disk => vec![],
+            RepoSource::Conf | RepoSource::Both => existing_repos(&self.conf_repos),
+        };
+        let roots = match self.source {
+            RepoSource::Conf => vec![],
+            RepoSource::Disk | RepoSource::Both => roots.to_vec(),
+        };
+
+        let mut found = HashSet::new();
+        conf_repos
+            .into_iter()
+            .chain(roots.into_iter().flat_map(move |root| index::indexed_repos(&self, &root)))
+            .filter(move |dir| found.insert(fs::canonicalize(dir).unwrap_or_else(|_| dir.clone())))
+    }
+
+    // Walks from the start, which is the root or a directory under it, and returns the repos found along with the
+    // directories looked into. The traversal stops at the root of a repo, unless nested repos are to be found too.
+    // Submodules are then found through git.
+    pub fn walk(&self, root: &Path, start: &Path) -> (Vec<PathBuf>, Vec<PathBuf>) {
+        let mut repos = Repos {
+            walker: self.clone().process_directories(root, start),
+            nested: self.nested,
+            submodules: vec![],
+            visited: vec![],
+        };
+        let found = repos.by_ref().collect();
+        (found, repos.visited)
+    }
+
+    // Symlinks can point to other file systems, so the walk only stays on the same file system when they are not followed.
+    // Walkdir reports a symlink pointing to one of its ancestors as an error, which breaks the cycle.
+    fn process_directories(self, root: &Path, start: &Path) -> FilterEntry<IntoIter, impl FnMut(&DirEntry) -> bool> {
+        let skip_matcher = skip_matcher(root, &self.filter_list)
+            .expect("failed to build the matcher for skipDirectories");
+        let mut ignore_files = IgnoreFiles::new(root, self.respect_gitignore);
+        let mut walker = WalkDir::new(start)
+            .follow_links(self.follow_links)
             .contents_first(false)
-            .same_file_system(true)
+            .same_file_system(!self.follow_links);
+        if let Some(max_depth) = self.max_depth {
+            let start_depth = start.strip_prefix(root).map_or(0, |path| path.components().count());
+            walker = walker.max_depth(max_depth.saturating_sub(start_depth));
+        }
+        walker
             .into_iter()
             .filter_entry(move |e| {
-                self.should_filter(e)
+                self.should_filter(e, &skip_matcher)
                     .expect(format!("failed to filter for entry {:#?}", e).as_str())
+                    && (e.depth() == 0 || !ignore_files.is_ignored(e.path()))
             })
     }
 
-    fn should_filter(&self, entry: &DirEntry) -> Result<bool, Box<dyn Error>> {
-        let path_string = entry
-            .path()
-            .to_str()
-            .expect("could not get path from the entry")
-            .trim_start_matches("./");
-
+    fn should_filter(&self, entry: &DirEntry, skip_matcher: &Gitignore) -> Result<bool, Box<dyn Error>> {
         if self.filter_hidden {
             return Ok(self.is_not_hidden(entry));
         }
@@ -44,12 +133,293 @@ impl DirectoryTreeOptions {
             return Ok(false);
         }
 
-        for ignore in self.filter_list.iter() {
-            if ignore.is_match(path_string) {
-                return Ok(false);
-            }
+        if entry.depth() > 0 && skip_matcher.matched(entry.path(), true).is_ignore() {
+            return Ok(false);
         }
 
         return Ok(true);
     }
 }
+
+struct Repos<P> {
+    walker: FilterEntry<IntoIter, P>,
+    nested: bool,
+    submodules: Vec<PathBuf>,
+    visited: Vec<PathBuf>,
+}
+
+impl<P: FnMut(&DirEntry) -> bool> Iterator for Repos<P> {
+    type Item = PathBuf;
+
+    fn next(&mut self) -> Option<PathBuf> {
+        if let Some(submodule) = self.submodules.pop() {
+            self.submodules.append(&mut submodules(&submodule));
+            self.visited.push(submodule.clone());
+            return Some(submodule);
+        }
+
+        loop {
+            let entry = match self.walker.next()? {
+                Ok(entry) => entry,
+                Err(err) => {
+                    if let (Some(path), Some(ancestor)) = (err.path(), err.loop_ancestor()) {
+                        println!("{} {} {} {}", "Skipping symlink loop at".yellow(), path.to_string_lossy().yellow(),
+                                 "pointing to".yellow(), ancestor.to_string_lossy().yellow());
+                    }
+                    continue;
+                }
+            };
+            if !entry.file_type().is_dir() {
+                continue;
+            }
+            if entry.file_name() == ".git" {
+                self.walker.skip_current_dir();
+                continue;
+            }
+            // Repos are looked into too, as they are looked at again when they stop being a repo
+            // or when their submodules change.
+            self.visited.push(entry.path().to_path_buf());
+            match RepoKind::of(entry.path()) {
+                // A bare repo has no worktree to look into.
+                Some(RepoKind::Bare) => self.walker.skip_current_dir(),
+                // The walker finds the submodules too when it looks into the worktree.
+                Some(_) if self.nested => {}
+                Some(_) => {
+                    self.walker.skip_current_dir();
+                    self.submodules = submodules(entry.path());
+                }
+                None => continue,
+            }
+            return Some(entry.path().to_path_buf());
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RepoKind {
+    Repo,
+    Worktree,
+    Submodule,
+    Bare,
+}
+
+impl RepoKind {
+    // A `.git` file points to the git dir of a linked worktree or of a submodule, which is kept
+    // under `.git/modules` of the parent repo.
+    pub fn of(dir: &Path) -> Option<RepoKind> {
+        let git_path = dir.join(".git");
+        if git_path.is_dir() {
+            return Some(RepoKind::Repo);
+        }
+        if git_path.is_file() {
+            let repo = Repository::open(dir).ok()?;
+            if repo.is_worktree() {
+                return Some(RepoKind::Worktree);
+            }
+            let components: Vec<_> = repo.path().components().map(|component| component.as_os_str().to_owned()).collect();
+            let is_submodule = components.windows(2).any(|pair| pair[0] == ".git" && pair[1] == "modules");
+            return Some(if is_submodule { RepoKind::Submodule } else { RepoKind::Repo });
+        }
+        if dir.join("HEAD").is_file() && dir.join("objects").is_dir() && dir.join("refs").is_dir() {
+            return Repository::open_bare(dir).ok().map(|_| RepoKind::Bare);
+        }
+        None
+    }
+
+    fn label(self) -> Option<&'static str> {
+        match self {
+            RepoKind::Repo => None,
+            RepoKind::Worktree => Some("worktree"),
+            RepoKind::Submodule => Some("submodule"),
+            RepoKind::Bare => Some("bare"),
+        }
+    }
+}
+
+// The path of the repo, labelled with its kind unless it is a regular repo.
+pub fn describe_repo(dir: &Path) -> String {
+    let path = dir.to_string_lossy().to_string();
+    match RepoKind::of(dir).and_then(RepoKind::label) {
+        Some(label) => format!("{} ({})", path, label),
+        None => path,
+    }
+}
+
+// Reports the listed repos which are missing on disk, or are not git repos.
+fn existing_repos(repos: &[PathBuf]) -> Vec<PathBuf> {
+    repos
+        .iter()
+        .filter(|repo| {
+            if !repo.exists() {
+                println!("{} {}", "Missing on disk:".yellow(), repo.to_string_lossy().yellow());
+                return false;
+            }
+            if RepoKind::of(repo).is_none() {
+                println!("{} {}", "Not a git repo:".yellow(), repo.to_string_lossy().yellow());
+                return false;
+            }
+            true
+        })
+        .cloned()
+        .collect()
+}
+
+// The checked out submodules of the repo, as the walker does not look into the worktree of a repo.
+fn submodules(dir: &Path) -> Vec<PathBuf> {
+    Repository::open(dir)
+        .and_then(|repo| {
+            repo.submodules().map(|submodules| {
+                submodules
+                    .iter()
+                    .map(|submodule| dir.join(submodule.path()))
+                    .filter(|path| path.join(".git").exists())
+                    .collect()
+            })
+        })
+        .unwrap_or_default()
+}
+
+// The `.ggignore` files, and the `.gitignore` files of enclosing git repos when respecting them, of the
+// directories from the root down to an entry. A file in a deeper directory takes precedence, and a
+// `.ggignore` takes precedence over the `.gitignore` in the same directory.
+struct IgnoreFiles {
+    walk_root: PathBuf,
+    // The canonical root, so that the patterns of ignore files above the root can be matched too.
+    root: PathBuf,
+    respect_gitignore: bool,
+    matchers: HashMap<PathBuf, Option<Gitignore>>,
+    in_repo: HashMap<PathBuf, bool>,
+}
+
+impl IgnoreFiles {
+    fn new(root: &Path, respect_gitignore: bool) -> IgnoreFiles {
+        IgnoreFiles {
+            walk_root: root.to_path_buf(),
+            root: fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf()),
+            respect_gitignore,
+            matchers: HashMap::new(),
+            in_repo: HashMap::new(),
+        }
+    }
+
+    fn is_ignored(&mut self, path: &Path) -> bool {
+        let path = &self.root.join(path.strip_prefix(&self.walk_root).unwrap_or(path));
+        let mut dirs: Vec<PathBuf> = path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&self.root))
+            .map(Path::to_path_buf)
+            .collect();
+        // .gitignore files of a repo enclosing the root apply too, like for the rest of its worktree.
+        if self.respect_gitignore {
+            let mut outer = self.root.parent().map(Path::to_path_buf);
+            while let Some(dir) = outer {
+                if !self.is_in_repo(&dir) {
+                    break;
+                }
+                outer = dir.parent().map(Path::to_path_buf);
+                dirs.push(dir);
+            }
+        }
+
+        for dir in dirs {
+            if let Some(matcher) = self.matcher(&dir) {
+                let matched = matcher.matched(path, true);
+                if matched.is_ignore() {
+                    return true;
+                }
+                if matched.is_whitelist() {
+                    return false;
+                }
+            }
+        }
+        false
+    }
+
+    fn matcher(&mut self, dir: &Path) -> Option<&Gitignore> {
+        if !self.matchers.contains_key(dir) {
+            let mut files = vec![];
+            if self.respect_gitignore && self.is_in_repo(dir) {
+                files.push(dir.join(".gitignore"));
+            }
+            files.push(dir.join(GG_IGNORE_FILE));
+
+            let mut builder = GitignoreBuilder::new(dir);
+            let mut found = false;
+            for file in files.into_iter().filter(|file| file.is_file()) {
+                found = true;
+                if let Some(err) = builder.add(&file) {
+                    println!("{} {}: {}", "failed to read".red(), file.to_string_lossy(), err);
+                }
+            }
+            let matcher = if found { builder.build().ok() } else { None };
+            self.matchers.insert(dir.to_path_buf(), matcher);
+        }
+        self.matchers.get(dir).and_then(Option::as_ref)
+    }
+
+    fn is_in_repo(&mut self, dir: &Path) -> bool {
+        if let Some(in_repo) = self.in_repo.get(dir) {
+            return *in_repo;
+        }
+        let in_repo = dir.join(".git").exists() || dir.parent().is_some_and(|parent| self.is_in_repo(parent));
+        self.in_repo.insert(dir.to_path_buf(), in_repo);
+        in_repo
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_skipped(root: &str, patterns: &[&str], dir: &str) -> bool {
+        let patterns: Vec<String> = patterns.iter().map(|pattern| pattern.to_string()).collect();
+        let matcher = skip_matcher(Path::new(root), &patterns).unwrap();
+        matcher.matched(Path::new(dir), true).is_ignore()
+    }
+
+    #[test]
+    fn should_skip_only_directories_with_the_exact_name() {
+        assert!(is_skipped("/ws", &["vendor"], "/ws/vendor"));
+        assert!(is_skipped("/ws", &["vendor"], "/ws/a/vendor"));
+        assert!(!is_skipped("/ws", &["vendor"], "/ws/vendor-tools"));
+        assert!(!is_skipped("/ws", &["vendor"], "/ws/my-vendor"));
+    }
+
+    #[test]
+    fn should_anchor_patterns_with_a_leading_slash_to_the_root() {
+        assert!(is_skipped("/ws", &["/build"], "/ws/build"));
+        assert!(!is_skipped("/ws", &["/build"], "/ws/a/build"));
+    }
+
+    #[test]
+    fn should_match_double_star_patterns_at_any_depth() {
+        assert!(is_skipped("/ws", &["**/tmp"], "/ws/tmp"));
+        assert!(is_skipped("/ws", &["**/tmp"], "/ws/a/b/tmp"));
+        assert!(!is_skipped("/ws", &["**/tmp"], "/ws/a/tmpfiles"));
+    }
+
+    #[test]
+    fn should_re_include_a_default_with_a_negated_pattern() {
+        assert!(is_skipped("/ws", &[".idea", ".DS_Store"], "/ws/.idea"));
+        assert!(!is_skipped("/ws", &[".idea", ".DS_Store", "!.idea"], "/ws/.idea"));
+        assert!(is_skipped("/ws", &[".idea", ".DS_Store", "!.idea"], "/ws/.DS_Store"));
+    }
+
+    #[test]
+    fn should_match_dots_literally() {
+        assert!(is_skipped("/ws", &["node.js"], "/ws/node.js"));
+        assert!(!is_skipped("/ws", &["node.js"], "/ws/node_js"));
+    }
+
+    // Walkdir yields the paths with the root as their prefix, like `./build` for the root `.`.
+    #[test]
+    fn should_match_paths_under_a_relative_root() {
+        assert!(is_skipped(".", &["/build"], "./build"));
+        assert!(!is_skipped(".", &["/build"], "./a/build"));
+        assert!(!is_skipped(".", &["vendor"], "./vendor-tools"));
+        assert!(is_skipped("./x", &["/build"], "./x/build"));
+        assert!(!is_skipped("./x", &["/build"], "./x/a/build"));
+        assert!(is_skipped("./x", &["**/tmp"], "./x/a/tmp"));
+    }
+}
@@ -4,10 +4,9 @@ use git2::{
     AutotagOption, Error as GitError, FetchOptions, RemoteCallbacks,
     Repository,
 };
-use regex::Regex;
 use std::path::PathBuf;
 
-use crate::dir::DirectoryTreeOptions;
+use crate::dir::{describe_repo, DirectoryTreeOptions};
 use crate::git::GitAction;
 use crate::input_args::InputArgs;
 use crate::progress::{ProgressReporter, ProgressTracker};
@@ -18,39 +17,35 @@ pub fn sub_command<'a, 'b>() -> App<'a, 'b> {
         .arg(Arg::with_name("PATH")
             .short("f")
             .takes_value(true)
-            .help("path at which to fetch the git repos"))
+            .multiple(true)
+            .number_of_values(1)
+            .help("path at which to fetch the git repos. Can be passed multiple times. Defaults to the roots in the conf or '.'"))
         .arg(Arg::with_name("traverse-hidden")
             .short("i")
             .help("traverse through hidden directories also")
         )
+        .arg(Arg::with_name("nested")
+            .long("nested")
+            .help("also find the repos nested in the worktree of other repos")
+        )
 }
 
-pub fn fetch(args: InputArgs, filter_list: Vec<Regex>) {
+pub fn fetch(args: InputArgs, dir_options: DirectoryTreeOptions, selection: RepoSelection) {
     let matches = args.get_matches();
     let filter_hidden = matches.is_present("traverse-hidden");
 
     let dir_tree_with_options = DirectoryTreeOptions {
-        filter_list: filter_list,
-        filter_hidden: filter_hidden,
+        filter_hidden,
+        nested: matches.is_present("nested"),
+        ..dir_options
     };
 
-    let root_path = args.get_root_path("PATH");
-    let root = root_path
-        .to_str()
-        .expect(format!("{}", "Error in converting directory to string".red()).as_str());
+    let roots = args.get_root_paths("PATH", &dir_tree_with_options.roots);
 
     let multi_bars = ProgressTracker::new(matches.value_of("jobs").and_then(|e| e.parse().ok()));
     dir_tree_with_options
-        .process_directories(root)
-        .flat_map(|dir| {
-            dir.ok().and_then(|d| {
-                if d.file_name().eq(".git") {
-                    d.path().parent().map(|e| e.to_path_buf())
-                } else {
-                    None
-                }
-            })
-        })
+        .find_repos(&roots)
+        .filter(|dir| selection.contains(dir))
         .map(|dir| GitFetch {
             dir,
             remote: "origin".to_string(),
@@ -67,7 +62,7 @@ pub struct GitFetch {
 
 impl<'a> GitAction for GitFetch {
     fn get_name(&self) -> String {
-        format!("{} from {:?}", self.remote, self.dir)
+        format!("{} from {}", self.remote, describe_repo(&self.dir))
     }
 
     fn git_action(&mut self, prog: &ProgressReporter) -> Result<String, GitError> {
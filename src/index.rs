@@ -0,0 +1,144 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::dir::{DirectoryTreeOptions, RepoKind, GG_IGNORE_FILE};
+
+// The repos found under a root, along with the directories looked into to find them.
+// Only the directories changed since the index was saved are looked into again.
+#[derive(Debug, Serialize, Deserialize)]
+struct RepoIndex {
+    // The root and the discovery options the index was built with.
+    key: String,
+    dirs: BTreeMap<PathBuf, DirStamp>,
+    repos: Vec<PathBuf>,
+}
+
+pub fn indexed_repos(options: &DirectoryTreeOptions, root: &Path) -> Vec<PathBuf> {
+    let key = index_key(options, root);
+    let index_file = index_file(&key);
+    let saved = match &index_file {
+        Some(index_file) if !options.rescan => read_index(index_file).filter(|index| index.key == key),
+        _ => None,
+    };
+
+    let (index, changed) = match saved {
+        Some(mut index) => {
+            let changed = refresh(&mut index, options, root);
+            (index, changed)
+        }
+        None => {
+            let (mut repos, dirs) = options.walk(root, root);
+            repos.sort();
+            let index = RepoIndex {
+                key,
+                dirs: dirs.into_iter().map(|dir| (dir.clone(), dir_stamp(&dir))).collect(),
+                repos,
+            };
+            (index, true)
+        }
+    };
+
+    // The index is only a cache, so failing to save it is not an error.
+    if let (Some(index_file), true) = (index_file, changed) {
+        let _ = write_index(&index_file, &index);
+    }
+    index.repos
+}
+
+// Looks into the changed directories again and returns whether anything changed.
+// Only the top most of them are walked, as that covers the ones under them.
+fn refresh(index: &mut RepoIndex, options: &DirectoryTreeOptions, root: &Path) -> bool {
+    // A repo which is no longer one is looked into again, even when the change is too recent to show in the
+    // modification times.
+    let mut stale: Vec<PathBuf> = index
+        .dirs
+        .iter()
+        .filter(|(dir, stamp)| dir_stamp(dir) != **stamp)
+        .map(|(dir, _)| dir.clone())
+        .chain(index.repos.iter().filter(|repo| RepoKind::of(repo).is_none()).cloned())
+        .collect();
+    stale.sort();
+
+    let mut changed: Vec<PathBuf> = vec![];
+    for dir in stale {
+        if !changed.iter().any(|changed_dir| dir.starts_with(changed_dir)) {
+            changed.push(dir);
+        }
+    }
+
+    for dir in changed.iter() {
+        index.dirs.retain(|indexed_dir, _| !indexed_dir.starts_with(dir));
+        index.repos.retain(|repo| !repo.starts_with(dir));
+        if dir.is_dir() {
+            let (repos, dirs) = options.walk(root, dir);
+            index.repos.extend(repos);
+            dirs.into_iter().for_each(|dir| {
+                let stamp = dir_stamp(&dir);
+                index.dirs.insert(dir, stamp);
+            });
+        }
+    }
+    index.repos.sort();
+    !changed.is_empty()
+}
+
+// The modification times of a directory, of its ignore files and of the submodules of a repo. A directory changes
+// when an entry is added to or removed from it, like the `.git` of a repo, and its ignore files decide which of its
+// directories are looked into. Submodules are added to `.gitmodules` and checked out under `.git/modules`.
+type DirStamp = Vec<Option<SystemTime>>;
+
+fn dir_stamp(dir: &Path) -> DirStamp {
+    [
+        dir.to_path_buf(),
+        dir.join(GG_IGNORE_FILE),
+        dir.join(".gitignore"),
+        dir.join(".gitmodules"),
+        dir.join(".git").join("modules"),
+    ]
+    .iter()
+    .map(|path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok())
+    .collect()
+}
+
+fn index_key(options: &DirectoryTreeOptions, root: &Path) -> String {
+    let canonical_root = fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+    format!(
+        "{:?}",
+        (root, canonical_root, &options.filter_list, options.filter_hidden, options.respect_gitignore,
+         options.nested, options.max_depth, options.follow_links)
+    )
+}
+
+// `$XDG_CACHE_HOME/gg`, which defaults to `~/.cache/gg`, with a file for each root and discovery options.
+fn index_file(key: &str) -> Option<PathBuf> {
+    let cache_home = env::var("XDG_CACHE_HOME")
+        .ok()
+        .filter(|cache_home| !cache_home.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var("HOME").ok().map(|home| Path::new(&home).join(".cache")))?;
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    Some(cache_home.join("gg").join(format!("index-{:016x}.json", hasher.finish())))
+}
+
+fn read_index(index_file: &Path) -> Option<RepoIndex> {
+    let file = File::open(index_file).ok()?;
+    serde_json::from_reader(file).ok()
+}
+
+fn write_index(index_file: &Path, index: &RepoIndex) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(dir) = index_file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temporary = index_file.with_extension("json.tmp");
+    serde_json::to_writer(File::create(&temporary)?, index)?;
+    fs::rename(&temporary, index_file)?;
+    Ok(())
+}
@@ -12,6 +12,7 @@ pub enum InputCommand {
     Clone,
     Branches,
     Config,
+    Checkout,
     Error,
 }
 
@@ -24,6 +25,7 @@ impl InputCommand {
             InputCommand::Clone => "clone",
             InputCommand::Branches => "branches",
             InputCommand::Config => "config",
+            InputCommand::Checkout => "checkout",
             _ => "unknown command",
         }
     }
@@ -72,6 +74,11 @@ impl<'a> InputArgs<'a> {
                 input_command: InputCommand::Config,
                 arg_matches: matches.to_owned(),
             }
+        } else if subcommand_name == InputCommand::Checkout.as_str() {
+            InputArgs {
+                input_command: InputCommand::Checkout,
+                arg_matches: matches.to_owned(),
+            }
         } else {
             InputArgs {
                 input_command: InputCommand::Error,
@@ -88,6 +95,15 @@ impl<'a> InputArgs<'a> {
         &self.arg_matches
     }
 
+    // The paths passed with the arg, else the roots from the conf, else the current directory.
+    pub fn get_root_paths(&self, arg_name: &str, conf_roots: &[PathBuf]) -> Vec<PathBuf> {
+        match self.arg_matches.values_of(arg_name) {
+            Some(paths) => paths.map(PathBuf::from).collect(),
+            None if !conf_roots.is_empty() => conf_roots.to_vec(),
+            None => vec![self.get_root_path(arg_name)],
+        }
+    }
+
     pub fn get_root_path(&self, arg_name: &str) -> PathBuf {
         match &self.arg_matches.value_of(arg_name) {
             Some(path) => Path::new(path).to_path_buf(),
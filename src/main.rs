@@ -9,6 +9,7 @@ extern crate serde_yaml;
 extern crate walkdir;
 
 use std::{mem, sync::Mutex};
+use std::path::PathBuf;
 use std::process;
 
 use clap::{crate_version, App, AppSettings, Arg};
@@ -16,27 +17,27 @@ use colored::*;
 use lazy_static::lazy_static;
 
 use crate::conf::SSHConfig;
+use crate::dir::{DirectoryTreeOptions, RepoSource};
 use crate::input_args::InputCommand;
 
 mod clone;
 mod branches;
+mod checkout;
 mod config;
 mod conf;
 mod create;
 mod dir;
 mod fetch;
 mod git;
+mod index;
 mod input_args;
+mod platform;
 mod status;
 
 mod progress;
 
 lazy_static! {
-   pub static ref SSH_CONF: Mutex<SSHConfig> =  Mutex::new(SSHConfig{
-        private_key: String::from(format!("{}/.ssh/id_rsa", std::env::var("HOME").expect("HOME env not found"))),
-        ssh_agent: false,
-        username: String::from("git"),
-   });
+   pub static ref SSH_CONF: Mutex<SSHConfig> =  Mutex::new(conf::default_ssh_config());
 }
 
 fn main() {
@@ -48,7 +49,7 @@ fn main() {
                 .short("c")
                 .global(true)
                 .takes_value(true)
-                .help("config file to use. Defaults to .ggConf"),
+                .help("config file to use. Defaults to the closest .ggConf.yaml in the current directory or its parents"),
         )
         .arg(
             Arg::with_name("jobs")
@@ -63,11 +64,62 @@ fn main() {
                         .map_err(|err| format!("{}", err))
                 }),
         )
+        .arg(
+            Arg::with_name("group")
+                .long("group")
+                .global(true)
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("only act on the repos of cloneRepos in this group. Can be passed multiple times"),
+        )
+        .arg(
+            Arg::with_name("tag")
+                .long("tag")
+                .global(true)
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("only act on the repos of cloneRepos with this tag. Can be passed multiple times"),
+        )
+        .arg(
+            Arg::with_name("max_depth")
+                .long("max-depth")
+                .global(true)
+                .takes_value(true)
+                .help("the maximum depth of the directories in which git repos are looked up")
+                .validator(|str| {
+                    str.parse()
+                        .map(|_: usize| ())
+                        .map_err(|err| format!("{}", err))
+                }),
+        )
+        .arg(
+            Arg::with_name("follow_links")
+                .long("follow-links")
+                .global(true)
+                .help("follow symlinks while looking up git repos, even to other file systems. Symlink loops are skipped"),
+        )
+        .arg(
+            Arg::with_name("rescan")
+                .long("rescan")
+                .global(true)
+                .help("look into every directory again instead of only the ones changed since the repo index was saved"),
+        )
+        .arg(
+            Arg::with_name("source")
+                .long("source")
+                .global(true)
+                .takes_value(true)
+                .possible_values(&["conf", "disk", "both"])
+                .help("act on the repos listed in cloneRepos of the conf, the repos found on disk or both. Defaults to disk"),
+        )
         .subcommand(status::sub_command())
         .subcommand(create::sub_command())
         .subcommand(fetch::sub_command())
         .subcommand(branches::sub_command())
         .subcommand(config::sub_command())
+        .subcommand(checkout::sub_command())
         .subcommand(clone::sub_command());
 
 
@@ -75,9 +127,21 @@ fn main() {
 
     let args = input_args::InputArgs::parse_inputs(global_matches.clone());
 
-    let conf = conf::read_conf_file(global_matches.value_of("conf").unwrap_or(".ggConf.yaml"))
+    let conf_file = conf::workspace_conf_file(global_matches.value_of("conf"));
+
+    // Validating has to happen before reading the conf, since reading fails on an invalid conf.
+    if args.input_command() == InputCommand::Config {
+        match args.get_matches().subcommand_name() {
+            Some("validate") => return config::validate(&conf_file),
+            Some("show") => return config::show(&conf_file),
+            _ => {}
+        }
+    }
+
+    let conf = conf::read_conf(&conf_file)
         .unwrap_or_else(|err| {
             println!("{} {}", "error while reading conf file:".red(), err.to_string().red());
+            println!("{}", "Run `gg config validate` for details".yellow());
             process::exit(1)
         });
 
@@ -88,13 +152,40 @@ fn main() {
         );
     }
 
+    let selector = conf::RepoSelector::from_matches(&global_matches);
+    let dir_options = DirectoryTreeOptions {
+        filter_list: conf.filter_list,
+        filter_hidden: false,
+        respect_gitignore: conf.respect_gitignore,
+        nested: false,
+        max_depth: global_matches.value_of("max_depth").map(|depth| depth.parse().unwrap()),
+        follow_links: global_matches.is_present("follow_links"),
+        roots: conf.roots.iter().map(PathBuf::from).collect(),
+        rescan: global_matches.is_present("rescan"),
+        source: global_matches
+            .value_of("source")
+            .or(conf.source.as_deref())
+            .and_then(RepoSource::from_str)
+            .unwrap_or(RepoSource::Disk),
+        conf_repos: conf
+            .clone_repos
+            .iter()
+            .filter(|repo| selector.is_selected(repo))
+            .map(|repo| PathBuf::from(&repo.local_path))
+            .collect(),
+    };
+
     match args.input_command() {
-        InputCommand::Status => status::status(args, conf.filter_list_regex),
-        InputCommand::Create => create::create(args),
-        InputCommand::Branches => branches::branches(args),
-        InputCommand::Config => config::config(args, conf.filter_list_regex, conf.filter_list, conf.clone_repos),
-        InputCommand::Clone => clone::clone(args, conf.clone_repos),
-        InputCommand::Fetch => fetch::fetch(args, conf.filter_list_regex),
+        InputCommand::Status => status::status(args, dir_options, selector.selection(&conf.clone_repos)),
+        InputCommand::Create => create::create(args, conf.create_options.unwrap_or_default()),
+        InputCommand::Branches => {
+            let selection = selector.selection(&conf.clone_repos);
+            branches::branches(args, dir_options, conf.clone_repos, selection)
+        }
+        InputCommand::Config => config::config(args, &conf_file, dir_options),
+        InputCommand::Clone => clone::clone(args, conf.clone_repos.into_iter().filter(|repo| selector.is_selected(repo)).collect()),
+        InputCommand::Fetch => fetch::fetch(args, dir_options, selector.selection(&conf.clone_repos)),
+        InputCommand::Checkout => checkout::checkout(args, dir_options, selector.selection(&conf.clone_repos)),
         InputCommand::Error => {}
     }
 }
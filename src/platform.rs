@@ -0,0 +1,634 @@
+use std::error::Error;
+use std::{env, fmt};
+
+use clap::{Arg, ArgMatches};
+use colored::*;
+use regex::Regex;
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::create::RepoOptions;
+
+const GITHUB_API: &str = "https://api.github.com";
+// Overrides the api url, for Github Enterprise installations.
+const GITHUB_API_ENV: &str = "GITHUB_API_URL";
+const PAGE_SIZE: u32 = 100;
+
+/// Arguments to select the repos listed by a platform. Shared by the subcommands that list repos.
+pub fn list_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("org")
+            .long("org")
+            .takes_value(true)
+            .conflicts_with("user")
+            .help("list all repos of the given organization from the platform"),
+        Arg::with_name("user")
+            .long("user")
+            .takes_value(true)
+            .help("list all repos of the given user from the platform"),
+        Arg::with_name("match")
+            .long("match")
+            .takes_value(true)
+            .validator(|str| Regex::new(&str).map(|_| ()).map_err(|err| format!("{}", err)))
+            .help("only select listed repos whose name matches the given regex"),
+        Arg::with_name("topic")
+            .long("topic")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("only select listed repos tagged with the given topic. Can be passed multiple times"),
+        Arg::with_name("skip_archived")
+            .long("skip-archived")
+            .help("skip listed repos which are archived"),
+        Arg::with_name("skip_forks")
+            .long("skip-forks")
+            .help("skip listed repos which are forks"),
+    ]
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RepoOwner<'a> {
+    Org(&'a str),
+    User(&'a str),
+}
+
+impl<'a> RepoOwner<'a> {
+    pub fn from_matches(matches: &'a ArgMatches) -> Option<RepoOwner<'a>> {
+        matches
+            .value_of("org")
+            .map(RepoOwner::Org)
+            .or_else(|| matches.value_of("user").map(RepoOwner::User))
+    }
+}
+
+/// A repo as listed by the platform.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlatformRepo {
+    pub name: String,
+    pub clone_url: String,
+    pub ssh_url: String,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default)]
+    pub fork: bool,
+    #[serde(default)]
+    pub topics: Vec<String>,
+}
+
+pub struct RepoFilter {
+    pub name: Option<Regex>,
+    pub topics: Vec<String>,
+    pub skip_archived: bool,
+    pub skip_forks: bool,
+}
+
+impl RepoFilter {
+    pub fn from_matches(matches: &ArgMatches) -> RepoFilter {
+        RepoFilter {
+            name: matches
+                .value_of("match")
+                .map(|name| Regex::new(name).expect("failed to construct regex")),
+            topics: matches
+                .values_of("topic")
+                .map(|topics| topics.map(String::from).collect())
+                .unwrap_or_default(),
+            skip_archived: matches.is_present("skip_archived"),
+            skip_forks: matches.is_present("skip_forks"),
+        }
+    }
+
+    pub fn is_match(&self, repo: &PlatformRepo) -> bool {
+        if self.skip_archived && repo.archived {
+            return false;
+        }
+        if self.skip_forks && repo.fork {
+            return false;
+        }
+        if !self.topics.iter().all(|topic| repo.topics.contains(topic)) {
+            return false;
+        }
+        match &self.name {
+            Some(name) => name.is_match(&repo.name),
+            None => true,
+        }
+    }
+}
+
+/// Lists all the repos of the owner, fetching every page from the platform.
+// The repos of other users only include their public repos. So the repos of the authenticated user are listed
+// through the api for the authenticated user, which includes their private repos too.
+pub fn list_repos(platform: GitPlatform, owner: &RepoOwner, token: &str) -> Result<Vec<PlatformRepo>, PlatformError> {
+    let client = reqwest::Client::new();
+    let authenticated = match owner {
+        RepoOwner::User(user) if !token.is_empty() => {
+            let returned_json = send(platform.authenticated_user_api(&client, token))?;
+            returned_json["login"].as_str().is_some_and(|login| login.eq_ignore_ascii_case(user))
+        }
+        _ => false,
+    };
+
+    let mut repos = vec![];
+    let mut page = 1;
+    loop {
+        let returned_json = send(platform.list_api(&client, owner, authenticated, token, page))?;
+        let page_repos: Vec<PlatformRepo> = serde_json::from_value(returned_json)
+            .map_err(|err| PlatformError::InvalidResponse(err.to_string()))?;
+
+        let is_last_page = page_repos.len() < PAGE_SIZE as usize;
+        repos.extend(page_repos);
+        if is_last_page {
+            return Ok(repos);
+        }
+        page += 1;
+    }
+}
+
+fn github_api() -> String {
+    env::var(GITHUB_API_ENV).unwrap_or_else(|_| GITHUB_API.to_string())
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GitPlatform {
+    Github,
+}
+
+impl GitPlatform {
+    pub fn from_str(platform: &str) -> GitPlatform {
+        match platform {
+            "github" => GitPlatform::Github,
+            _ => panic!("{}", "Unsupported platform".red()),
+        }
+    }
+
+    fn authorize(&self, request: RequestBuilder, token: &str) -> RequestBuilder {
+        match *self {
+            GitPlatform::Github => {
+                let request = request
+                    .header("Accept", "application/vnd.github.v3+json")
+                    .header("Content-Type", "application/json");
+                // Public repos can be listed without a token.
+                if token.is_empty() {
+                    request
+                } else {
+                    request.header("Authorization", format!("token {}", token))
+                }
+            }
+        }
+    }
+
+    pub fn create_api(&self, client: &Client, repo_name: &str, token: &str, options: &RepoOptions) -> RequestBuilder {
+        match *self {
+            GitPlatform::Github => {
+                let mut data = Map::new();
+                data.insert("name".to_string(), json!(repo_name));
+                if let Some(private) = options.private {
+                    data.insert("private".to_string(), json!(private));
+                }
+                if let Some(description) = &options.description {
+                    data.insert("description".to_string(), json!(description));
+                }
+
+                // Repos generated from a template get their contents from the template.
+                // So the init options are only applicable while creating a new repo.
+                if let Some(template) = &options.template {
+                    if let Some(org) = &options.org {
+                        data.insert("owner".to_string(), json!(org));
+                    }
+                    let request = client
+                        .post(format!("{}/repos/{}/generate", github_api(), template).as_str())
+                        .json(&data);
+                    return self
+                        .authorize(request, token)
+                        .header("Accept", "application/vnd.github.baptiste-preview+json");
+                }
+
+                if let Some(homepage) = &options.homepage {
+                    data.insert("homepage".to_string(), json!(homepage));
+                }
+                if let Some(auto_init) = options.auto_init {
+                    data.insert("auto_init".to_string(), json!(auto_init));
+                }
+                if let Some(gitignore_template) = &options.gitignore_template {
+                    data.insert("gitignore_template".to_string(), json!(gitignore_template));
+                }
+                if let Some(license_template) = &options.license_template {
+                    data.insert("license_template".to_string(), json!(license_template));
+                }
+
+                let url = match &options.org {
+                    Some(org) => format!("{}/orgs/{}/repos", github_api(), org),
+                    None => format!("{}/user/repos", github_api()),
+                };
+                self.authorize(client.post(url.as_str()).json(&data), token)
+            }
+        }
+    }
+
+    pub fn fork_api(&self, client: &Client, source: &str, token: &str, org: Option<&String>) -> RequestBuilder {
+        match *self {
+            GitPlatform::Github => {
+                let mut data = Map::new();
+                if let Some(org) = org {
+                    data.insert("organization".to_string(), json!(org));
+                }
+                let request = client
+                    .post(format!("{}/repos/{}/forks", github_api(), source).as_str())
+                    .json(&data);
+                self.authorize(request, token)
+            }
+        }
+    }
+
+    pub fn authenticated_user_api(&self, client: &Client, token: &str) -> RequestBuilder {
+        match *self {
+            GitPlatform::Github => self.authorize(client.get(format!("{}/user", github_api()).as_str()), token),
+        }
+    }
+
+    // `authenticated` is whether the owner is the authenticated user.
+    pub fn list_api(&self, client: &Client, owner: &RepoOwner, authenticated: bool, token: &str, page: u32) -> RequestBuilder {
+        match *self {
+            GitPlatform::Github => {
+                let mut query = vec![("per_page", PAGE_SIZE.to_string()), ("page", page.to_string())];
+                let url = match owner {
+                    RepoOwner::Org(org) => format!("{}/orgs/{}/repos", github_api(), org),
+                    RepoOwner::User(_) if authenticated => {
+                        // Only the repos owned by the user, not the ones of their orgs or collaborations.
+                        query.push(("affiliation", "owner".to_string()));
+                        format!("{}/user/repos", github_api())
+                    }
+                    RepoOwner::User(user) => format!("{}/users/{}/repos", github_api(), user),
+                };
+                let request = client.get(url.as_str()).query(&query);
+                // Topics are only returned with the mercy preview.
+                self.authorize(request, token)
+                    .header("Accept", "application/vnd.github.mercy-preview+json")
+            }
+        }
+    }
+
+    // Lists the latest commit. Fails while the repo is empty, like a fork which is still being created.
+    pub fn latest_commit_api(&self, client: &Client, full_name: &str, token: &str) -> RequestBuilder {
+        match *self {
+            GitPlatform::Github => {
+                let request = client
+                    .get(format!("{}/repos/{}/commits", github_api(), full_name).as_str())
+                    .query(&[("per_page", "1")]);
+                self.authorize(request, token)
+            }
+        }
+    }
+
+    pub fn topics_api(&self, client: &Client, full_name: &str, token: &str, topics: &[String]) -> RequestBuilder {
+        match *self {
+            GitPlatform::Github => {
+                let request = client
+                    .put(format!("{}/repos/{}/topics", github_api(), full_name).as_str())
+                    .json(&json!({ "names": topics }));
+                self.authorize(request, token)
+                    .header("Accept", "application/vnd.github.mercy-preview+json")
+            }
+        }
+    }
+
+    pub fn default_branch_api(&self, client: &Client, full_name: &str, token: &str, branch: &str) -> RequestBuilder {
+        match *self {
+            GitPlatform::Github => {
+                let repo_name = full_name.rsplit('/').next().unwrap_or(full_name);
+                let request = client
+                    .patch(format!("{}/repos/{}", github_api(), full_name).as_str())
+                    .json(&json!({ "name": repo_name, "default_branch": branch }));
+                self.authorize(request, token)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PlatformError {
+    Auth(String),
+    AlreadyExists(String),
+    RateLimited(String),
+    Network(reqwest::Error),
+    Api { status: StatusCode, message: String },
+    InvalidResponse(String),
+}
+
+impl PlatformError {
+    /// Each kind of failure exits with a distinct code so that scripts can react to it.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            PlatformError::Api { .. } => 1,
+            PlatformError::Auth(_) => 2,
+            PlatformError::AlreadyExists(_) => 3,
+            PlatformError::RateLimited(_) => 4,
+            PlatformError::Network(_) => 5,
+            PlatformError::InvalidResponse(_) => 6,
+        }
+    }
+}
+
+impl fmt::Display for PlatformError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlatformError::Auth(message) => write!(f, "authentication failed, check the access token: {}", message),
+            PlatformError::AlreadyExists(message) => write!(f, "repo already exists: {}", message),
+            PlatformError::RateLimited(message) => write!(f, "rate limited by the platform, try again later: {}", message),
+            PlatformError::Network(err) => write!(f, "could not reach the platform: {}", err),
+            PlatformError::Api { status, message } => write!(f, "platform returned {}: {}", status, message),
+            PlatformError::InvalidResponse(message) => write!(f, "unexpected response from the platform: {}", message),
+        }
+    }
+}
+
+impl Error for PlatformError {}
+
+impl From<reqwest::Error> for PlatformError {
+    fn from(err: reqwest::Error) -> Self {
+        PlatformError::Network(err)
+    }
+}
+
+/// Sends the request and returns the json body of a successful response.
+/// Failed responses are classified based on their status code and body.
+pub fn send(request: RequestBuilder) -> Result<Value, PlatformError> {
+    let mut response = request.send()?;
+    let status = response.status();
+    let rate_limit_exhausted = response
+        .headers()
+        .get("X-RateLimit-Remaining")
+        .and_then(|remaining| remaining.to_str().ok())
+        == Some("0");
+    let body = response.text()?;
+
+    if status.is_success() {
+        // Some successful responses, like 204 No Content, have no body.
+        if body.trim().is_empty() {
+            return Ok(Value::Null);
+        }
+        return serde_json::from_str(&body).map_err(|err| PlatformError::InvalidResponse(err.to_string()));
+    }
+
+    // Errors from proxies or gateways may not be json, the status code is still classified.
+    let returned_json = serde_json::from_str(&body).unwrap_or(Value::Null);
+    let message = error_message(&returned_json);
+    Err(match status.as_u16() {
+        429 => PlatformError::RateLimited(message),
+        403 if rate_limit_exhausted => PlatformError::RateLimited(message),
+        401 | 403 => PlatformError::Auth(message),
+        422 if message.contains("already exists") => PlatformError::AlreadyExists(message),
+        _ => PlatformError::Api { status, message },
+    })
+}
+
+// Github sends a generic message along with the specific errors, if any.
+fn error_message(returned_json: &Value) -> String {
+    let message = returned_json["message"].as_str().unwrap_or("no error message").to_string();
+    let errors: Vec<&str> = returned_json["errors"]
+        .as_array()
+        .map(|errors| errors.iter().filter_map(|error| error["message"].as_str()).collect())
+        .unwrap_or_default();
+    if errors.is_empty() {
+        message
+    } else {
+        format!("{} ({})", message, errors.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc::{channel, Receiver};
+    use std::sync::Mutex;
+    use std::thread;
+
+    use lazy_static::lazy_static;
+
+    use super::*;
+
+    lazy_static! {
+        // The api url is read from the environment, so the tests pointing it at a server can not run in parallel.
+        static ref API_URL: Mutex<()> = Mutex::new(());
+    }
+
+    fn response(status: &str, headers: &[&str], body: &str) -> String {
+        let mut response = format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+            status,
+            body.len()
+        );
+        for header in headers {
+            response.push_str(&format!("{}\r\n", header));
+        }
+        response.push_str(&format!("\r\n{}", body));
+        response
+    }
+
+    // Serves the responses to the requests in order, one for each connection.
+    // Returns the url of the server and the request lines it received, like `GET /user HTTP/1.1`.
+    fn serve(responses: Vec<String>) -> (String, Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    let lower = line.to_lowercase();
+                    if let Some(length) = lower.strip_prefix("content-length:") {
+                        content_length = length.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+                sender.send(request_line.trim().to_string()).unwrap();
+            }
+        });
+        (url, receiver)
+    }
+
+    fn serve_once(status: &str, headers: &[&str], body: &str) -> String {
+        serve(vec![response(status, headers, body)]).0
+    }
+
+    fn create_with_api(url: &str) -> Result<Value, PlatformError> {
+        let _guard = API_URL.lock().unwrap_or_else(|err| err.into_inner());
+        env::set_var(GITHUB_API_ENV, url);
+        let client = Client::new();
+        let request =
+            GitPlatform::Github.create_api(&client, "repo", "token", &RepoOptions::default());
+        let result = send(request);
+        env::remove_var(GITHUB_API_ENV);
+        result
+    }
+
+    fn create_with_response(
+        status: &str,
+        headers: &[&str],
+        body: &str,
+    ) -> Result<Value, PlatformError> {
+        create_with_api(&serve_once(status, headers, body))
+    }
+
+    #[test]
+    fn should_return_json_of_successful_response() {
+        let returned_json =
+            create_with_response("201 Created", &[], r#"{"full_name": "owner/repo"}"#).unwrap();
+
+        assert_eq!(returned_json["full_name"], "owner/repo");
+    }
+
+    #[test]
+    fn should_accept_successful_response_without_body() {
+        assert_eq!(
+            create_with_response("204 No Content", &[], "").unwrap(),
+            Value::Null
+        );
+    }
+
+    #[test]
+    fn should_map_unauthorized_to_auth_error() {
+        let err =
+            create_with_response("401 Unauthorized", &[], r#"{"message": "Bad credentials"}"#)
+                .unwrap_err();
+
+        assert!(matches!(err, PlatformError::Auth(ref message) if message == "Bad credentials"));
+        assert_eq!(err.exit_code(), 2);
+    }
+
+    #[test]
+    fn should_map_forbidden_to_auth_error() {
+        let err = create_with_response("403 Forbidden", &["X-RateLimit-Remaining: 42"], "{}")
+            .unwrap_err();
+
+        assert!(matches!(err, PlatformError::Auth(_)));
+        assert_eq!(err.exit_code(), 2);
+    }
+
+    #[test]
+    fn should_map_forbidden_with_exhausted_rate_limit_to_rate_limited() {
+        let err =
+            create_with_response("403 Forbidden", &["X-RateLimit-Remaining: 0"], "{}").unwrap_err();
+
+        assert!(matches!(err, PlatformError::RateLimited(_)));
+        assert_eq!(err.exit_code(), 4);
+    }
+
+    #[test]
+    fn should_map_too_many_requests_to_rate_limited() {
+        let err = create_with_response("429 Too Many Requests", &[], "{}").unwrap_err();
+
+        assert!(matches!(err, PlatformError::RateLimited(_)));
+        assert_eq!(err.exit_code(), 4);
+    }
+
+    #[test]
+    fn should_map_existing_repo_to_already_exists() {
+        let body = r#"{"message": "Repository creation failed.", "errors": [{"message": "name already exists on this account"}]}"#;
+        let err = create_with_response("422 Unprocessable Entity", &[], body).unwrap_err();
+
+        assert!(matches!(
+            err,
+            PlatformError::AlreadyExists(ref message)
+                if message == "Repository creation failed. (name already exists on this account)"
+        ));
+        assert_eq!(err.exit_code(), 3);
+    }
+
+    #[test]
+    fn should_keep_status_of_error_response_without_json_body() {
+        let err = create_with_response(
+            "502 Bad Gateway",
+            &["Content-Type: text/html"],
+            "<html>bad gateway</html>",
+        )
+        .unwrap_err();
+
+        assert!(matches!(
+            err,
+            PlatformError::Api { status, ref message }
+                if status == StatusCode::BAD_GATEWAY && message == "no error message"
+        ));
+        assert_eq!(err.exit_code(), 1);
+    }
+
+    #[test]
+    fn should_map_invalid_successful_response_to_invalid_response() {
+        let err = create_with_response("200 OK", &[], "not json").unwrap_err();
+
+        assert!(matches!(err, PlatformError::InvalidResponse(_)));
+        assert_eq!(err.exit_code(), 6);
+    }
+
+    #[test]
+    fn should_map_unreachable_platform_to_network_error() {
+        // The port is free once the listener is dropped, so the connection is refused.
+        let url = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+
+        let err = create_with_api(&url).unwrap_err();
+
+        assert!(matches!(err, PlatformError::Network(_)));
+        assert_eq!(err.exit_code(), 5);
+    }
+
+    fn list_with_api(url: &str, owner: &RepoOwner, token: &str) -> Result<Vec<PlatformRepo>, PlatformError> {
+        let _guard = API_URL.lock().unwrap_or_else(|err| err.into_inner());
+        env::set_var(GITHUB_API_ENV, url);
+        let result = list_repos(GitPlatform::Github, owner, token);
+        env::remove_var(GITHUB_API_ENV);
+        result
+    }
+
+    const REPOS: &str = r#"[{"name": "r", "clone_url": "https://github.com/me/r.git", "ssh_url": "git@github.com:me/r.git"}]"#;
+
+    #[test]
+    fn should_list_all_repos_of_the_authenticated_user() {
+        let (url, requests) = serve(vec![
+            response("200 OK", &[], r#"{"login": "Me"}"#),
+            response("200 OK", &[], REPOS),
+        ]);
+
+        let repos = list_with_api(&url, &RepoOwner::User("me"), "token").unwrap();
+
+        assert_eq!(repos.len(), 1);
+        assert_eq!(requests.recv().unwrap(), "GET /user HTTP/1.1");
+        assert_eq!(requests.recv().unwrap(), "GET /user/repos?per_page=100&page=1&affiliation=owner HTTP/1.1");
+    }
+
+    #[test]
+    fn should_list_public_repos_of_other_users() {
+        let (url, requests) = serve(vec![
+            response("200 OK", &[], r#"{"login": "me"}"#),
+            response("200 OK", &[], REPOS),
+        ]);
+
+        list_with_api(&url, &RepoOwner::User("other"), "token").unwrap();
+
+        assert_eq!(requests.recv().unwrap(), "GET /user HTTP/1.1");
+        assert_eq!(requests.recv().unwrap(), "GET /users/other/repos?per_page=100&page=1 HTTP/1.1");
+    }
+
+    #[test]
+    fn should_list_public_repos_of_users_without_a_token() {
+        let (url, requests) = serve(vec![response("200 OK", &[], REPOS)]);
+
+        list_with_api(&url, &RepoOwner::User("me"), "").unwrap();
+
+        assert_eq!(requests.recv().unwrap(), "GET /users/me/repos?per_page=100&page=1 HTTP/1.1");
+    }
+}
@@ -1,10 +1,10 @@
 use clap::{App, Arg, SubCommand};
 use colored::*;
 use git2::{Error as GitError, Repository, StatusOptions};
-use regex::Regex;
 use std::path::PathBuf;
 
-use crate::dir::DirectoryTreeOptions;
+use crate::conf::RepoSelection;
+use crate::dir::{describe_repo, DirectoryTreeOptions};
 use crate::git::GitAction;
 use crate::input_args::InputArgs;
 use crate::progress::{ProgressReporter, ProgressTracker};
@@ -15,41 +15,38 @@ pub fn sub_command<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("PATH")
                 .short("f")
                 .takes_value(true)
-                .help("path at which to create the local repo"),
+                .multiple(true)
+                .number_of_values(1)
+                .help("path at which to look for the git repos. Can be passed multiple times. Defaults to the roots in the conf or '.'"),
         )
         .arg(
             Arg::with_name("traverse-hidden")
                 .short("i")
                 .help("traverse through hidden directories also"),
         )
+        .arg(
+            Arg::with_name("nested")
+                .long("nested")
+                .help("also find the repos nested in the worktree of other repos"),
+        )
 }
 
-pub fn status(args: InputArgs, filter_list: Vec<Regex>) {
+pub fn status(args: InputArgs, dir_options: DirectoryTreeOptions, selection: RepoSelection) {
     let matches = args.get_matches();
     let filter_hidden = matches.is_present("traverse-hidden");
 
     let dir_tree_with_options = DirectoryTreeOptions {
-        filter_list,
         filter_hidden,
+        nested: matches.is_present("nested"),
+        ..dir_options
     };
 
-    let root_path = args.get_root_path("PATH");
-    let root = root_path
-        .to_str()
-        .expect(format!("{}", "Error in converting directory to string".red()).as_str());
+    let roots = args.get_root_paths("PATH", &dir_tree_with_options.roots);
 
     let multi_bars = ProgressTracker::new(matches.value_of("jobs").and_then(|e| e.parse().ok()));
     dir_tree_with_options
-        .process_directories(root)
-        .flat_map(|dir| {
-            dir.ok().and_then(|d| {
-                if d.file_name().eq(".git") {
-                    d.path().parent().map(|e| e.to_path_buf())
-                } else {
-                    None
-                }
-            })
-        })
+        .find_repos(&roots)
+        .filter(|dir| selection.contains(dir))
         .map(|dir| GitStatus { dir })
         .for_each(|status| multi_bars.start_task(status));
     multi_bars.join().unwrap();
@@ -61,7 +58,7 @@ pub struct GitStatus {
 
 impl<'a> GitAction for GitStatus {
     fn get_name(&self) -> String {
-        self.dir.to_string_lossy().to_string()
+        describe_repo(&self.dir)
     }
 
     fn git_action(&mut self, _progress: &ProgressReporter) -> Result<String, GitError> {
@@ -72,6 +69,9 @@ impl<'a> GitAction for GitStatus {
             .exclude_submodules(false);
 
         let repo = Repository::open(self.dir.clone())?;
+        if repo.is_bare() {
+            return Ok(format!("{}", "bare repository without a worktree".yellow()));
+        }
 
         let git_statuses = repo.statuses(Some(&mut opts))?;
         let mut statuses_in_dir = vec![];